@@ -2,12 +2,15 @@ mod constant;
 mod parser;
 mod visitor;
 
-pub use constant::{Constant, Loc};
+pub use constant::{CaretPos, Constant, Loc};
 pub use parser::parse as parse_ast;
+pub use parser::parse_text as parse_ast_text;
+pub use parser::read as read_file;
 pub use parser::ParsedFile;
 
 #[cfg(test)]
 mod tests {
+    use crate::files::InMemory;
     use std::path::Path;
 
     fn remove_absolute_paths(string: String) -> String {
@@ -16,7 +19,9 @@ mod tests {
     }
 
     fn test_fixture(ruby_file_path: &str, expectation_file_path: &str) {
-        let parsed_file = super::parse_ast(Path::new("./"), Path::new(ruby_file_path));
+        let text = std::fs::read_to_string(ruby_file_path).unwrap();
+        let source = InMemory::new(Path::new("./"), [(ruby_file_path, text)]);
+        let parsed_file = super::parse_ast(&source, Path::new(ruby_file_path));
 
         let actual = remove_absolute_paths(format!("{parsed_file:#?}"));
 
@@ -1,23 +1,25 @@
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+use crate::intern::{PathSymbol, Symbol};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Constant {
-    pub scope: Option<String>,
-    pub name: String,
+    pub scope: Option<Symbol>,
+    pub name: Symbol,
     pub loc: Loc,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Loc {
-    pub path: PathBuf,
-    pub root_path: PathBuf,
+    pub path: PathSymbol,
+    pub root_path: PathSymbol,
     pub begin: CaretPos,
     pub end: CaretPos,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct CaretPos {
     pub line: usize,
     pub column: usize,
@@ -27,53 +29,54 @@ impl Constant {
     pub fn nestings(&self) -> Vec<String> {
         let mut nestings = Vec::new();
 
-        let unwrapped_scope = self.scope.clone().unwrap_or_default();
+        let unwrapped_scope = self.scope.map(Symbol::as_str).unwrap_or_default();
         let mut remaining_parts: Vec<&str> = unwrapped_scope.split("::").collect();
 
         while let Some(nesting_part) = remaining_parts.pop() {
             let mut parts: Vec<&str> = remaining_parts.clone();
             parts.push(nesting_part);
-            parts.push(&self.name);
+            parts.push(self.name.as_str());
             nestings.push(parts.join("::"));
         }
 
-        nestings.push(self.name.clone());
+        nestings.push(self.name.as_str().to_owned());
         nestings
     }
 }
 
 impl Constant {
     pub fn qualified(&self) -> String {
-        qualified(&self.scope, &self.name)
+        qualified(self.scope, self.name)
     }
 }
 
 impl Loc {
-    pub fn relative_path(&self) -> &Path {
-        self.path.strip_prefix(&self.root_path).unwrap()
+    pub fn relative_path(&self) -> &'static Path {
+        self.path.as_path().strip_prefix(self.root_path.as_path()).unwrap()
     }
 }
 
-fn qualified(scope: &Option<String>, name: &str) -> String {
+fn qualified(scope: Option<Symbol>, name: Symbol) -> String {
     if let Some(scope) = scope {
         format!("{}::{}", scope, name)
     } else {
-        name.to_owned()
+        name.as_str().to_owned()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{CaretPos, Constant, Loc};
-    use std::{path::PathBuf, str::FromStr};
+    use crate::intern::{PathSymbol, Symbol};
+    use std::path::Path;
 
     fn constant() -> Constant {
         Constant {
-            scope: Some("A::B::C".to_owned()),
-            name: "InC".to_owned(),
+            scope: Some(Symbol::intern("A::B::C")),
+            name: Symbol::intern("InC"),
             loc: Loc {
-                path: PathBuf::from_str("./fixtures/nested_classes.rb").unwrap(),
-                root_path: PathBuf::from_str("./").unwrap(),
+                path: PathSymbol::intern(Path::new("./fixtures/nested_classes.rb")),
+                root_path: PathSymbol::intern(Path::new("./")),
                 begin: CaretPos { line: 1, column: 1 },
                 end: CaretPos { line: 1, column: 1 },
             },
@@ -1,8 +1,10 @@
 use super::constant::Constant;
 use super::visitor;
+use crate::{files::FileSource, intern::PathSymbol};
 use lib_ruby_parser::{traverse::visitor::Visitor, Parser, ParserOptions};
 use line_col::LineColLookup;
 use std::path::{Path, PathBuf};
+use tracing::instrument;
 
 #[derive(Debug)]
 pub struct ParsedFile {
@@ -11,28 +13,35 @@ pub struct ParsedFile {
     pub references: Vec<Constant>,
 }
 
-pub fn parse(root_path: &Path, path: &Path) -> ParsedFile {
-    let text = std::fs::read_to_string(path).unwrap();
-    let (definitions, references) = parse_text(&text, root_path, path);
+pub fn parse(source: &dyn FileSource, path: &Path) -> ParsedFile {
+    parse_text(&read(source, path), source.root(), path)
+}
 
-    ParsedFile {
-        path: path.to_owned(),
-        definitions,
-        references,
-    }
+/// Reading a file the walk just found only fails when it's removed in between, which checking once doesn't cater for.
+pub fn read(source: &dyn FileSource, path: &Path) -> String {
+    source.read(path).unwrap_or_else(|error| panic!("failed to read {}: {}", path.display(), error))
 }
 
-fn parse_text(text: &str, root_path: &Path, path: &Path) -> (Vec<Constant>, Vec<Constant>) {
+/// Parses `text` as the contents of `path`.
+#[instrument(skip_all, fields(path = %path.strip_prefix(root_path).unwrap_or(path).display()))]
+pub fn parse_text(text: &str, root_path: &Path, path: &Path) -> ParsedFile {
     let parser = Parser::new(text, ParserOptions::default());
     let ast = parser.do_parse().ast;
 
-    if ast.is_none() {
-        return (Vec::new(), Vec::new());
-    }
+    let (definitions, references) = match ast {
+        Some(ast) => {
+            let line_lookup = LineColLookup::new(text);
+            let mut visitor = visitor::Visitor::new(PathSymbol::intern(root_path), PathSymbol::intern(path), &line_lookup);
+            visitor.visit(&ast);
 
-    let line_lookup = LineColLookup::new(text);
-    let mut visitor = visitor::Visitor::new(root_path, path, &line_lookup);
-    visitor.visit(&ast.unwrap());
+            (visitor.definitions, visitor.references)
+        }
+        None => (Vec::new(), Vec::new()),
+    };
 
-    (visitor.definitions, visitor.references)
+    ParsedFile {
+        path: path.to_owned(),
+        definitions,
+        references,
+    }
 }
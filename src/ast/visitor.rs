@@ -1,5 +1,3 @@
-use std::path::{Path, PathBuf};
-
 use lib_ruby_parser::{
     nodes::{self},
     traverse::visitor,
@@ -11,20 +9,21 @@ use super::{
     constant::{CaretPos, Constant},
     Loc,
 };
+use crate::intern::{PathSymbol, Symbol};
 
 pub struct Visitor<'a> {
-    pub path: PathBuf,
-    pub root_path: PathBuf,
+    pub path: PathSymbol,
+    pub root_path: PathSymbol,
     pub line_lookup: &'a LineColLookup<'a>,
     pub definitions: Vec<Constant>,
     pub references: Vec<Constant>,
 }
 
 impl<'a> Visitor<'a> {
-    pub fn new(root_path: &Path, path: &Path, line_lookup: &'a LineColLookup<'a>) -> Self {
+    pub fn new(root_path: PathSymbol, path: PathSymbol, line_lookup: &'a LineColLookup<'a>) -> Self {
         Self {
-            path: path.to_owned(),
-            root_path: root_path.to_owned(),
+            path,
+            root_path,
             line_lookup,
             definitions: Vec::new(),
             references: Vec::new(),
@@ -36,8 +35,8 @@ impl<'a> Visitor<'a> {
         let (end_line, end_column) = self.line_lookup.get(parser_loc.begin);
 
         Loc {
-            path: self.path.clone(),
-            root_path: self.root_path.clone(),
+            path: self.path,
+            root_path: self.root_path,
             begin: CaretPos {
                 line: begin_line,
                 column: begin_column,
@@ -86,18 +85,18 @@ fn fetch_const_loc(name: &Node) -> lib_ruby_parser::Loc {
     }
 }
 
-fn nest_constants(_path: &Path, parent_name: &str, child_constants: Vec<Constant>) -> Vec<Constant> {
+fn nest_constants(parent_name: &str, child_constants: Vec<Constant>) -> Vec<Constant> {
     let mut constants = Vec::new();
 
     for child_constant in child_constants {
         let scope = if let Some(scope) = child_constant.scope {
-            format!("{}::{}", parent_name, scope)
+            Symbol::intern(&format!("{}::{}", parent_name, scope))
         } else {
-            parent_name.to_owned()
+            Symbol::intern(parent_name)
         };
 
         constants.push(Constant {
-            name: child_constant.name.clone(),
+            name: child_constant.name,
             loc: child_constant.loc,
             scope: Some(scope),
         });
@@ -113,11 +112,11 @@ impl<'a> visitor::Visitor for Visitor<'a> {
 
         let definition = Constant {
             scope: None,
-            name: name.clone(),
+            name: Symbol::intern(&name),
             loc: self.build_loc(loc),
         };
 
-        let mut visitor = Visitor::new(&self.root_path, &self.path, self.line_lookup);
+        let mut visitor = Visitor::new(self.root_path, self.path, self.line_lookup);
 
         if let Some(body) = node.body.as_ref() {
             visitor.visit(body);
@@ -125,8 +124,8 @@ impl<'a> visitor::Visitor for Visitor<'a> {
 
         self.definitions.push(definition);
 
-        self.definitions.append(&mut nest_constants(&self.path, &name, visitor.definitions));
-        self.references.append(&mut nest_constants(&self.path, &name, visitor.references));
+        self.definitions.append(&mut nest_constants(&name, visitor.definitions));
+        self.references.append(&mut nest_constants(&name, visitor.references));
     }
 
     fn on_module(&mut self, node: &nodes::Module) {
@@ -135,26 +134,26 @@ impl<'a> visitor::Visitor for Visitor<'a> {
 
         let definition = Constant {
             scope: None,
-            name: name.clone(),
+            name: Symbol::intern(&name),
             loc: self.build_loc(loc),
         };
 
-        let mut visitor = Visitor::new(&self.root_path, &self.path, self.line_lookup);
+        let mut visitor = Visitor::new(self.root_path, self.path, self.line_lookup);
 
         if let Some(body) = node.body.as_ref() {
             visitor.visit(body);
         }
 
         self.definitions.push(definition);
-        self.definitions.append(&mut nest_constants(&self.path, &name, visitor.definitions));
-        self.references.append(&mut nest_constants(&self.path, &name, visitor.references));
+        self.definitions.append(&mut nest_constants(&name, visitor.definitions));
+        self.references.append(&mut nest_constants(&name, visitor.references));
     }
 
     fn on_const(&mut self, node: &nodes::Const) {
         let name = fetch_const_const_name(node);
 
         let reference = Constant {
-            name,
+            name: Symbol::intern(&name),
             loc: self.build_loc(node.expression_l),
             scope: None,
         };
@@ -166,7 +165,7 @@ impl<'a> visitor::Visitor for Visitor<'a> {
         let name = fetch_casn_const_name(node);
 
         let definition = Constant {
-            name,
+            name: Symbol::intern(&name),
             scope: None,
             loc: self.build_loc(node.name_l),
         };
@@ -0,0 +1,201 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{
+    ast::{self, CaretPos, Constant, Loc},
+    files::FileSource,
+    intern::{PathSymbol, Symbol},
+};
+
+/// Parse results on disk, keyed by the hash of a file's contents, the packlite version and the cache format, so unchanged files
+/// aren't parsed again. Entries don't depend on where the file lives, which lets every application share them.
+pub struct ParseCache {
+    directory: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    definitions: Vec<CachedConstant>,
+    references: Vec<CachedConstant>,
+}
+
+/// A constant without its location's paths, which are the same for every constant of a file.
+#[derive(Serialize, Deserialize)]
+pub struct CachedConstant {
+    pub scope: Option<Symbol>,
+    pub name: Symbol,
+    pub begin: CaretPos,
+    pub end: CaretPos,
+}
+
+impl CachedConstant {
+    pub fn new(constant: &Constant) -> Self {
+        Self {
+            scope: constant.scope,
+            name: constant.name,
+            begin: constant.loc.begin,
+            end: constant.loc.end,
+        }
+    }
+
+    /// The constant again, as found in the file at `path` under `root_path`.
+    pub fn constant(&self, path: PathSymbol, root_path: PathSymbol) -> Constant {
+        Constant {
+            scope: self.scope,
+            name: self.name,
+            loc: Loc {
+                path,
+                root_path,
+                begin: self.begin,
+                end: self.end,
+            },
+        }
+    }
+}
+
+/// Part of every key along with the packlite version. Bumped whenever what's cached changes shape, so builds that differ
+/// only in that never read each other's entries.
+const FORMAT: u32 = 1;
+
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+impl ParseCache {
+    pub fn new(directory: &Path) -> Self {
+        Self { directory: directory.to_owned() }
+    }
+
+    pub fn parse(&self, source: &dyn FileSource, path: &Path) -> ast::ParsedFile {
+        self.parse_text(&ast::read_file(source, path), source.root(), path)
+    }
+
+    /// Like `ast::parse_ast_text`, for contents that were already read.
+    pub fn parse_text(&self, text: &str, root_path: &Path, path: &Path) -> ast::ParsedFile {
+        let entry_path = self.entry_path(text);
+
+        if let Some(entry) = self.read(&entry_path) {
+            let (path_symbol, root_path) = (PathSymbol::intern(path), PathSymbol::intern(root_path));
+            let constants = |cached: Vec<CachedConstant>| cached.iter().map(|constant| constant.constant(path_symbol, root_path)).collect();
+
+            return ast::ParsedFile {
+                path: path.to_owned(),
+                definitions: constants(entry.definitions),
+                references: constants(entry.references),
+            };
+        }
+
+        let parsed_file = ast::parse_ast_text(text, root_path, path);
+        self.write(&entry_path, &parsed_file);
+        parsed_file
+    }
+
+    /// Removes every entry, including those of other packlite versions.
+    pub fn clear(&self) {
+        if self.directory.exists() {
+            std::fs::remove_dir_all(&self.directory).unwrap_or_else(|error| panic!("failed to remove {}: {}", self.directory.display(), error));
+        }
+    }
+
+    /// Where the incremental state of the project at `root_path` is kept, as of the git revision `rev` or in the working
+    /// directory, so checking a revision doesn't replace the state of the working directory.
+    pub fn state_path(&self, root_path: &Path, rev: Option<&str>) -> PathBuf {
+        let key = match rev {
+            Some(rev) => format!("{}\0rev\0{}", root_path.display(), rev),
+            None => root_path.to_string_lossy().into_owned(),
+        };
+        self.directory.join("state").join(versioned_hash(key.as_bytes()))
+    }
+
+    fn entry_path(&self, text: &str) -> PathBuf {
+        let key = versioned_hash(text.as_bytes());
+        self.directory.join(&key[..2]).join(&key[2..])
+    }
+
+    fn read(&self, entry_path: &Path) -> Option<Entry> {
+        let bytes = std::fs::read(entry_path).ok()?;
+
+        match bincode::deserialize(&bytes) {
+            Ok(entry) => Some(entry),
+            Err(error) => {
+                warn!("ignoring unreadable cache entry {}: {}", entry_path.display(), error);
+                None
+            }
+        }
+    }
+
+    /// Failing to write only costs a parse next time, so errors are logged rather than fatal.
+    fn write(&self, entry_path: &Path, parsed_file: &ast::ParsedFile) {
+        let cached = |constants: &[Constant]| constants.iter().map(CachedConstant::new).collect();
+        let entry = Entry {
+            definitions: cached(&parsed_file.definitions),
+            references: cached(&parsed_file.references),
+        };
+
+        if let Err(error) = write_atomically(entry_path, &bincode::serialize(&entry).unwrap()) {
+            warn!("failed to write cache entry {}: {}", entry_path.display(), error);
+        }
+    }
+}
+
+/// Writes next to `path` and renames, so concurrent runs never read a partially written file.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let temporary_path = path.with_extension(format!("{}-{}.tmp", std::process::id(), TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)));
+    let result = std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| std::fs::write(&temporary_path, bytes))
+        .and_then(|_| std::fs::rename(&temporary_path, path));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary_path);
+    }
+    result
+}
+
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text))
+}
+
+fn versioned_hash(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0]);
+    hasher.update(FORMAT.to_le_bytes());
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::ParseCache;
+    use crate::files::InMemory;
+
+    #[test]
+    fn test_cached_parse() {
+        let directory = std::env::temp_dir().join(format!("packlite-cache-test-{}", std::process::id()));
+        let cache = ParseCache::new(&directory);
+        let text = "module A\n  class B\n    C\n  end\nend\n";
+
+        let first = InMemory::new(Path::new("/first"), [("a/b.rb", text)]);
+        let parsed = format!("{:?}", cache.parse(&first, Path::new("/first/a/b.rb")));
+
+        // same contents at another path hit the entry, with locations pointing at the new path
+        let second = InMemory::new(Path::new("/second"), [("b.rb", text)]);
+        let cached = format!("{:?}", cache.parse(&second, Path::new("/second/b.rb")));
+
+        let entries = entry_count(&directory);
+        cache.clear();
+        assert_eq!(entries, 1);
+        assert!(!directory.exists());
+        assert_eq!(parsed.replace("/first/a/b.rb", "/second/b.rb").replace("/first", "/second"), cached);
+    }
+
+    fn entry_count(directory: &Path) -> usize {
+        std::fs::read_dir(directory).unwrap().map(|shard| std::fs::read_dir(shard.unwrap().path()).unwrap().count()).sum()
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{files, git};
+
+pub const CONFIG_FILE_NAME: &str = "packlite.yml";
+
+/// Project wide settings, read from packlite.yml in the root directory. Every setting is optional.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// globs, relative to the root directory, of files to skip before parsing, or of directories not to walk into.
+    /// A skipped directory counts as one entry in the summary printed after a check
+    pub exclude: Vec<String>,
+    /// whether file discovery follows symlinks, or skips them
+    pub follow_symlinks: bool,
+    /// check references into the root package according to its package.yml, instead of always allowing them
+    pub enforce_root: bool,
+    /// whether parse results and the resolved project are cached between runs. Off unless it's turned on here, as the
+    /// cache writes into `cache_directory` inside the project
+    pub cache: bool,
+    /// where parse results are cached, relative to the root directory
+    pub cache_directory: PathBuf,
+    /// check several project roots in one run instead of the root directory. Every application has its own packs,
+    /// and its own packlite.yml for the settings above, apart from the cache which they share
+    pub applications: Vec<Application>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Application {
+    /// directory of the application, relative to the root directory. Also the name it's reported under
+    pub root: String,
+    /// roots of the other applications whose constants references in this one may resolve to. Those references are
+    /// only resolved, never validated: constants of another application belong to none of this one's packs
+    #[serde(default)]
+    pub uses: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            follow_symlinks: true,
+            enforce_root: false,
+            cache: false,
+            cache_directory: PathBuf::from("tmp/cache/packlite"),
+            applications: Vec::new(),
+        }
+    }
+}
+
+/// Reads the packlite.yml of `root_path`, as of the git revision `rev` when there's one, like the rest of the files.
+pub fn load(root_path: &Path, rev: Option<&str>) -> Result<Config, String> {
+    let config_path = root_path.join(CONFIG_FILE_NAME);
+
+    let contents = match rev {
+        Some(rev) => git::read_files(root_path, rev, &[PathBuf::from(CONFIG_FILE_NAME)])?.pop().map(|(_, contents)| contents),
+        None if config_path.exists() => Some(std::fs::read_to_string(&config_path).map_err(|error| format!("failed to read {}: {}", config_path.display(), error))?),
+        None => None,
+    };
+
+    let config = match contents {
+        Some(contents) => serde_yaml::from_str::<Config>(&contents).map_err(|error| format!("invalid {}: {}", config_path.display(), error))?,
+        None => Config::default(),
+    };
+    files::build_glob_set(&config.exclude).map_err(|error| format!("invalid {}: {}", config_path.display(), error))?;
+
+    Ok(config)
+}
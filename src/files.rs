@@ -1,105 +1,634 @@
+mod source;
+
+pub use source::{Directory, FileSource, InMemory, Overlay};
+
 use std::{
+    collections::{BTreeMap, HashSet},
     ffi::OsStr,
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
-use jwalk::WalkDir;
-use rayon::iter::{ParallelBridge, ParallelIterator};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{WalkBuilder, WalkState};
+use itertools::Itertools;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
-use tracing::instrument;
+use tracing::{instrument, warn};
+
+use crate::intern::Symbol;
 
 #[derive(Serialize, Deserialize)]
 struct SerializablePackage {
-    enforce_dependencies: bool,
-    enforce_privacy: bool,
+    enforce_dependencies: Enforcement,
+    enforce_privacy: Enforcement,
     dependencies: Option<Vec<String>>,
+    #[serde(default)]
+    ignored_private_constants: Vec<String>,
+    #[serde(default)]
+    ignored_dependencies: Vec<String>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    metadata: Metadata,
 }
 
-#[derive(Serialize)]
+/// Free-form `metadata` from package.yml (owner, slack channel, etc.), passed through untouched.
+pub type Metadata = BTreeMap<String, serde_yaml::Value>;
+
+#[derive(Serialize, Clone)]
 pub struct Package {
-    pub name: String,
+    /// interned, as violations and references refer to packs by name
+    pub name: Symbol,
     pub root: PathBuf,
-    pub enforce_dependencies: bool,
-    pub enforce_privacy: bool,
+    pub enforce_dependencies: Enforcement,
+    pub enforce_privacy: Enforcement,
     pub dependencies: Option<Vec<String>>,
+    /// private constants of this pack that other packs may reference anyway
+    pub ignored_private_constants: Vec<String>,
+    /// constants this pack may reference without declaring a dependency on the pack defining them
+    pub ignored_dependencies: Vec<String>,
+    /// globs, relative to the project root, of files outside this pack's directory that it owns
+    pub include: Vec<String>,
+    /// globs, relative to the project root, of files this pack doesn't own even though they're in its directory or included
+    pub exclude: Vec<String>,
+    pub metadata: Metadata,
 }
 
-enum SearchBy<'a> {
-    Extension(&'a str),
-    FileName(&'a str),
+/// `enforce_dependencies` / `enforce_privacy`, written as `true`, `false` or `strict` in package.yml.
+/// Violations of a strict pack are errors and never get added to deprecated_references.yml.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "SerializableEnforcement", into = "SerializableEnforcement")]
+pub enum Enforcement {
+    Disabled,
+    Enabled,
+    Strict,
 }
 
-#[instrument(skip_all)]
-pub fn all_ruby_files(root_path: &Path, package_paths: &[PathBuf]) -> Vec<PathBuf> {
-    let ruby_files: Vec<PathBuf> = paths_to_scan(root_path, package_paths).iter().flat_map(|path| walkdir(path, SearchBy::Extension("rb"))).collect();
-    ruby_files.into_iter().par_bridge().map(|file| std::fs::canonicalize(file).unwrap()).collect()
+/// Serialized the way package.yml writes it, so the project dump reads the same.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum SerializableEnforcement {
+    Bool(bool),
+    Named(String),
+}
+
+impl TryFrom<SerializableEnforcement> for Enforcement {
+    type Error = String;
+
+    fn try_from(enforcement: SerializableEnforcement) -> Result<Self, Self::Error> {
+        match enforcement {
+            SerializableEnforcement::Bool(false) => Ok(Enforcement::Disabled),
+            SerializableEnforcement::Bool(true) => Ok(Enforcement::Enabled),
+            SerializableEnforcement::Named(name) if name == "strict" => Ok(Enforcement::Strict),
+            SerializableEnforcement::Named(name) => Err(format!("expected true, false or strict, got '{}'", name)),
+        }
+    }
+}
+
+impl From<Enforcement> for SerializableEnforcement {
+    fn from(enforcement: Enforcement) -> Self {
+        match enforcement {
+            Enforcement::Disabled => SerializableEnforcement::Bool(false),
+            Enforcement::Enabled => SerializableEnforcement::Bool(true),
+            Enforcement::Strict => SerializableEnforcement::Named("strict".to_owned()),
+        }
+    }
+}
+
+impl Enforcement {
+    pub fn is_enabled(&self) -> bool {
+        *self != Enforcement::Disabled
+    }
+
+    pub fn is_strict(&self) -> bool {
+        *self == Enforcement::Strict
+    }
+}
+
+/// A `key=value` filter matched against a package's metadata.
+#[derive(Debug, Clone)]
+pub struct MetadataFilter {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for MetadataFilter {
+    type Err = String;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        match filter.split_once('=') {
+            Some((key, value)) => Ok(Self {
+                key: key.to_owned(),
+                value: value.to_owned(),
+            }),
+            None => Err(format!("expected a metadata filter in the form of key=value, got '{}'", filter)),
+        }
+    }
+}
+
+impl Package {
+    pub fn matches_metadata(&self, filters: &[MetadataFilter]) -> bool {
+        filters
+            .iter()
+            .all(|filter| self.metadata.get(&filter.key).is_some_and(|value| metadata_value_matches(value, &filter.value)))
+    }
+}
+
+/// package.yml lists constants the way deprecated_references.yml does (`::Foo::Bar`), definitions are stored as `Foo::Bar`.
+fn normalize_constant_name(name: &str) -> String {
+    name.trim_start_matches("::").to_owned()
+}
+
+/// Compiles package-name style globs (`packs/platform/*`), where `*` never crosses a `/` and `**` does.
+pub fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|error| format!("invalid glob '{}': {}", pattern, error.kind()))?;
+        builder.add(glob);
+    }
+
+    builder.build().map_err(|error| error.to_string())
 }
 
+/// Like `build_glob_set`, for the globs of a package, which `all_packages` already checked.
+pub fn package_glob_set(patterns: &[String]) -> GlobSet {
+    build_glob_set(patterns).expect("the globs of packages are checked when they're loaded")
+}
+
+fn metadata_value_matches(value: &serde_yaml::Value, expected: &str) -> bool {
+    match value {
+        serde_yaml::Value::String(string) => string == expected,
+        serde_yaml::Value::Bool(bool) => bool.to_string() == expected,
+        serde_yaml::Value::Number(number) => number.to_string() == expected,
+        serde_yaml::Value::Sequence(values) => values.iter().any(|value| metadata_value_matches(value, expected)),
+        _ => false,
+    }
+}
+
+/// The package defined by package.yml in the root directory, which owns every file no other package does.
+pub const ROOT_PACKAGE_NAME: &str = "root";
+
+/// Ignore file read in every directory on top of `.gitignore` and `.git/info/exclude`.
+pub const IGNORE_FILE_NAME: &str = ".packliteignore";
+
+#[derive(Clone)]
+pub struct WalkOptions {
+    /// skip whatever `.gitignore`, `.git/info/exclude` and `.packliteignore` files exclude
+    pub respect_ignore_files: bool,
+    /// walk into symlinked directories and pick up symlinked files, instead of skipping symlinks entirely
+    pub follow_symlinks: bool,
+    pub exclude: Exclude,
+}
+
+/// `exclude` globs, relative to the root directory, along with how many entries each of them skipped. An excluded directory
+/// isn't walked into, so it counts as a single entry however many files are under it. Clones share the counts.
+#[derive(Clone)]
+pub struct Exclude {
+    patterns: Vec<String>,
+    globs: GlobSet,
+    skipped_entries: Arc<[AtomicUsize]>,
+}
+
+impl Exclude {
+    pub fn new(patterns: Vec<String>) -> Result<Self, String> {
+        Ok(Self {
+            globs: build_glob_set(&patterns)?,
+            skipped_entries: patterns.iter().map(|_| AtomicUsize::new(0)).collect(),
+            patterns,
+        })
+    }
+
+    /// Whether a rule matches `relative_path` or one of the directories it's in, so `vendor/bundle` excludes everything
+    /// under it.
+    pub fn excludes(&self, relative_path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+
+        let matched = relative_path
+            .ancestors()
+            .take_while(|path| !path.as_os_str().is_empty())
+            .find_map(|path| self.globs.matches(path).first().copied());
+
+        match matched {
+            Some(index) => {
+                self.skipped_entries[index].fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The rules that skipped at least one entry, and how many entries they skipped.
+    pub fn skipped_entries(&self) -> Vec<(&str, usize)> {
+        self.patterns
+            .iter()
+            .zip(self.skipped_entries.iter())
+            .map(|(pattern, skipped)| (pattern.as_str(), skipped.load(Ordering::Relaxed)))
+            .filter(|(_, skipped)| *skipped > 0)
+            .collect()
+    }
+}
+
+/// Everything file discovery found, as canonical paths.
+#[derive(Clone)]
+pub struct ProjectFiles {
+    pub ruby_files: Vec<PathBuf>,
+    pub package_files: Vec<PathBuf>,
+}
+
+/// The files whose references get validated. Definitions always come from the whole project.
+pub enum FileFilter {
+    All,
+    /// absolute paths of files, or of directories containing them
+    Only(Vec<PathBuf>),
+}
+
+impl FileFilter {
+    pub fn contains(&self, path: &Path) -> bool {
+        match self {
+            FileFilter::All => true,
+            FileFilter::Only(paths) => paths.iter().any(|checked_path| path.starts_with(checked_path)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum FileKind {
+    Ruby,
+    Package,
+    /// only reported by `walkdir`
+    Directory,
+}
+
+impl FileKind {
+    fn of(path: &Path) -> Option<Self> {
+        if path.extension().unwrap_or_else(|| OsStr::new("")) == "rb" {
+            Some(FileKind::Ruby)
+        } else if path.file_name().is_some_and(|file_name| file_name == "package.yml") {
+            Some(FileKind::Package)
+        } else {
+            None
+        }
+    }
+}
+
+/// `package_paths` are packwerk style globs (`packs/*`, `components/**/`) of the directories whose package.yml is a pack,
+/// when empty every package.yml is. The root package is always included. Fails on the first package.yml that can't be
+/// read or parsed.
 #[instrument(skip_all)]
-pub fn all_packages(root_path: &Path, package_paths: &[PathBuf]) -> Vec<Package> {
-    let package_files: Vec<PathBuf> = paths_to_scan(root_path, package_paths)
-        .iter()
-        .flat_map(|path| walkdir(path, SearchBy::FileName("package.yml")))
-        .collect();
-
-    package_files
-        .into_iter()
-        .par_bridge()
+pub fn all_packages(source: &dyn FileSource, package_paths: &[String]) -> Result<Vec<Package>, String> {
+    let package_paths = build_glob_set(&package_paths.iter().map(|package_path| normalize_package_path(package_path)).collect::<Vec<_>>()).map_err(|error| format!("{} in --package-paths", error))?;
+
+    source
+        .files()
+        .package_files
+        .par_iter()
         .filter_map(|package_file| {
-            let package_root = package_file.parent().unwrap();
-            let absolute_package_root = std::fs::canonicalize(package_root).unwrap();
-            let absolute_project_root = std::fs::canonicalize(root_path).unwrap();
-            let package_name = absolute_package_root.strip_prefix(&absolute_project_root).unwrap().to_string_lossy().to_string();
-            let package_yaml: SerializablePackage = serde_yaml::from_str(&std::fs::read_to_string(package_file).unwrap()).unwrap();
-
-            Some(Package {
-                name: if package_name.is_empty() { "root".to_string() } else { package_name },
+            let absolute_package_root = package_file.parent().unwrap().to_owned();
+            let package_name = absolute_package_root.strip_prefix(source.root()).unwrap().to_string_lossy().to_string();
+
+            if !package_name.is_empty() && !package_paths.is_empty() && !package_paths.is_match(&package_name) {
+                return None;
+            }
+
+            let package_yaml: SerializablePackage = match source
+                .read(package_file)
+                .map_err(|error| error.to_string())
+                .and_then(|yaml| serde_yaml::from_str(&yaml).map_err(|error| error.to_string()))
+                .and_then(|package_yaml: SerializablePackage| {
+                    for globs in [package_yaml.dependencies.as_deref().unwrap_or_default(), &package_yaml.include, &package_yaml.exclude] {
+                        build_glob_set(globs)?;
+                    }
+                    Ok(package_yaml)
+                }) {
+                Ok(package_yaml) => package_yaml,
+                Err(error) => return Some(Err(format!("invalid {}: {}", package_file.display(), error))),
+            };
+
+            Some(Ok(Package {
+                name: Symbol::intern(if package_name.is_empty() { ROOT_PACKAGE_NAME } else { &package_name }),
                 root: absolute_package_root,
                 enforce_dependencies: package_yaml.enforce_dependencies,
                 enforce_privacy: package_yaml.enforce_privacy,
                 dependencies: package_yaml.dependencies,
-            })
+                ignored_private_constants: package_yaml.ignored_private_constants.iter().map(|name| normalize_constant_name(name)).collect(),
+                ignored_dependencies: package_yaml.ignored_dependencies.iter().map(|name| normalize_constant_name(name)).collect(),
+                include: package_yaml.include,
+                exclude: package_yaml.exclude,
+                metadata: package_yaml.metadata,
+            }))
         })
         .collect()
 }
 
+fn normalize_package_path(package_path: &str) -> String {
+    package_path.trim_start_matches("./").trim_end_matches('/').to_owned()
+}
+
+/// Returns the canonical paths of all ruby and package.yml files under `directory`, a directory of the project at
+/// `root_path`, sorted, each one once no matter how many symlinks lead to it. The directories below `directory` that
+/// were walked into, up to `max_depth`, are returned as well.
+/// Broken symlinks, symlink loops and symlinks leading out of the project are reported and skipped.
+/// `visit_ruby_file` is called on the walking threads as soon as a ruby file is found, while the walk goes on.
 #[instrument(skip_all)]
-fn paths_to_scan(root_path: &Path, package_paths: &[PathBuf]) -> Vec<PathBuf> {
-    let mut paths_to_scan: Vec<PathBuf> = Vec::new();
-
-    if package_paths.is_empty() {
-        paths_to_scan.push(root_path.to_owned())
-    } else {
-        for package_path in package_paths {
-            paths_to_scan.push(root_path.join(package_path))
-        }
+fn walkdir(root_path: &Path, walk_options: &WalkOptions, directory: &Path, max_depth: Option<usize>, visit_ruby_file: &(dyn Fn(&Path) + Sync)) -> Vec<(FileKind, PathBuf)> {
+    let absolute_root_path = std::fs::canonicalize(root_path).unwrap();
+
+    // ignore files in the directories above `directory` apply as well
+    let mut builder = WalkBuilder::new(directory);
+    builder
+        .standard_filters(walk_options.respect_ignore_files)
+        .hidden(false)
+        .require_git(false)
+        .follow_links(walk_options.follow_symlinks)
+        .max_depth(max_depth);
+
+    // excluded directories are never entered
+    let exclude = walk_options.exclude.clone();
+    let relative_root_path = root_path.to_owned();
+    builder.filter_entry(move |entry| {
+        let is_excluded_directory = || entry.depth() > 0 && entry.file_type().is_some_and(|file_type| file_type.is_dir()) && exclude.excludes(entry.path().strip_prefix(&relative_root_path).unwrap());
+        entry.file_name() != ".git" && !is_excluded_directory()
+    });
+
+    if walk_options.respect_ignore_files {
+        builder.add_custom_ignore_filename(IGNORE_FILE_NAME);
     }
 
-    paths_to_scan
-}
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let found = Mutex::new(HashSet::new());
 
-fn walkdir(root_path: &Path, search: SearchBy) -> Vec<PathBuf> {
-    WalkDir::new(root_path)
-        .into_iter()
-        .filter_map(|entry| {
-            let entry = entry.unwrap();
+    builder.build_parallel().run(|| {
+        let sender = sender.clone();
+        let absolute_root_path = &absolute_root_path;
+        let found = &found;
 
-            if entry.file_type().is_dir() {
-                return None;
-            }
+        Box::new(move |entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    warn!("skipping: {}", error);
+                    return WalkState::Continue;
+                }
+            };
 
             let path = entry.path();
 
-            let search_match = match search {
-                SearchBy::Extension(extension) => path.extension().unwrap_or_else(|| OsStr::new("")) == extension,
-                SearchBy::FileName(file_name) => path.file_name().unwrap() == file_name,
+            if entry.file_type().is_some_and(|file_type| file_type.is_dir()) {
+                if let Ok(absolute_path) = std::fs::canonicalize(path) {
+                    if entry.depth() > 0 && absolute_path.starts_with(absolute_root_path) {
+                        sender.send((FileKind::Directory, absolute_path)).unwrap();
+                    }
+                }
+                return WalkState::Continue;
+            }
+
+            if entry.file_type().is_none_or(|file_type| file_type.is_symlink()) {
+                return WalkState::Continue;
+            }
+
+            let kind = match FileKind::of(path) {
+                Some(kind) => kind,
+                None => return WalkState::Continue,
             };
 
-            if search_match {
-                Some(path)
-            } else {
-                None
+            if walk_options.exclude.excludes(path.strip_prefix(root_path).unwrap()) {
+                return WalkState::Continue;
+            }
+
+            match std::fs::canonicalize(path) {
+                Ok(absolute_path) if absolute_path.starts_with(absolute_root_path) => {
+                    if !found.lock().unwrap().insert(absolute_path.clone()) {
+                        return WalkState::Continue;
+                    }
+
+                    if kind == FileKind::Ruby {
+                        visit_ruby_file(&absolute_path);
+                    }
+                    sender.send((kind, absolute_path)).unwrap()
+                }
+                Ok(absolute_path) => warn!("skipping {}, it links to {} outside of the project", path.display(), absolute_path.display()),
+                Err(error) => warn!("skipping broken symlink {}: {}", path.display(), error),
             }
+
+            WalkState::Continue
         })
-        .collect()
+    });
+
+    drop(sender);
+    receiver.into_iter().sorted().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{all_packages, build_glob_set, Directory, Enforcement, Exclude, FileSource, InMemory, WalkOptions};
+
+    #[test]
+    fn test_enforcement() {
+        let parse = |yaml: &str| serde_yaml::from_str::<Enforcement>(yaml);
+
+        assert_eq!(parse("true").unwrap(), Enforcement::Enabled);
+        assert_eq!(parse("false").unwrap(), Enforcement::Disabled);
+        assert_eq!(parse("strict").unwrap(), Enforcement::Strict);
+        assert!(parse("sometimes").is_err());
+
+        let serialize = |enforcement: Enforcement| serde_json::to_string(&enforcement).unwrap();
+        assert_eq!(serialize(Enforcement::Enabled), "true");
+        assert_eq!(serialize(Enforcement::Disabled), "false");
+        assert_eq!(serialize(Enforcement::Strict), "\"strict\"");
+    }
+
+    #[test]
+    fn test_build_glob_set() {
+        let globs = build_glob_set(&["packs/platform/*".to_owned(), "packs/exact".to_owned(), "components/**/core".to_owned()]).unwrap();
+
+        assert!(globs.is_match("packs/platform/auth"));
+        assert!(!globs.is_match("packs/platform/auth/nested"));
+        assert!(globs.is_match("packs/exact"));
+        assert!(!globs.is_match("packs/exact_not"));
+        assert!(globs.is_match("components/a/b/core"));
+        assert!(globs.is_match("components/core"));
+
+        assert_eq!(build_glob_set(&["packs/[a".to_owned()]).unwrap_err(), "invalid glob 'packs/[a': unclosed character class; missing ']'");
+        let source = InMemory::new(
+            Path::new("/project"),
+            [("package.yml", "enforce_dependencies: true\nenforce_privacy: true\ndependencies:\n  - packs/{a\n")],
+        );
+        assert!(all_packages(&source, &[]).is_err_and(|error| error.starts_with("invalid /project/package.yml: invalid glob 'packs/{a'")));
+    }
+
+    #[test]
+    fn test_exclude() {
+        let exclude = Exclude::new(vec!["db/migrate".to_owned(), "lib".to_owned(), "**/*_spec.rb".to_owned()]).unwrap();
+
+        assert!(exclude.excludes(Path::new("db/migrate")));
+        assert!(exclude.excludes(Path::new("db/migrate/20220101_create_users.rb")));
+        assert!(!exclude.excludes(Path::new("db/schema.rb")));
+        assert!(exclude.excludes(Path::new("lib/tasks/deploy.rb")));
+        assert!(!exclude.excludes(Path::new("packs/lib.rb")));
+        assert!(!exclude.excludes(Path::new("packs/a/lib/a.rb")));
+        assert!(exclude.excludes(Path::new("packs/a/spec/a_spec.rb")));
+        assert_eq!(exclude.skipped_entries(), [("db/migrate", 2), ("lib", 1), ("**/*_spec.rb", 1)]);
+    }
+
+    #[test]
+    fn test_walk_skips_excluded_directories() {
+        let root_path = std::env::temp_dir().join(format!("packlite-exclude-test-{}", std::process::id()));
+        for path in ["app/models/user.rb", "vendor/bundle/gems/rails/base.rb", "vendor/bundle/gems/rack/rack.rb", "vendor/tools.rb"] {
+            std::fs::create_dir_all(root_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root_path.join(path), "").unwrap();
+        }
+
+        let walk_options = WalkOptions {
+            respect_ignore_files: false,
+            follow_symlinks: false,
+            exclude: Exclude::new(vec!["vendor/bundle".to_owned()]).unwrap(),
+        };
+        let source = Directory::new(&root_path, walk_options.clone());
+        let ruby_files = source.files().ruby_files.iter().map(|path| path.strip_prefix(source.root()).unwrap().to_owned()).collect::<Vec<_>>();
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        assert_eq!(ruby_files, [Path::new("app/models/user.rb"), Path::new("vendor/tools.rb")]);
+        // the directory is skipped as a whole, so the files in it are never seen
+        assert_eq!(walk_options.exclude.skipped_entries(), [("vendor/bundle", 1)]);
+    }
+
+    #[test]
+    fn test_directory_update() {
+        let root_path = std::env::temp_dir().join(format!("packlite-update-test-{}", std::process::id()));
+        let write = |path: &str| {
+            std::fs::create_dir_all(root_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root_path.join(path), "").unwrap();
+        };
+        write("app/models/user.rb");
+        write("app/models/post.rb");
+
+        let walk_options = WalkOptions {
+            respect_ignore_files: false,
+            follow_symlinks: false,
+            exclude: Exclude::new(Vec::new()).unwrap(),
+        };
+        let mut source = Directory::new(&root_path, walk_options);
+        let ruby_files = |source: &Directory| source.files().ruby_files.iter().map(|path| path.strip_prefix(source.root()).unwrap().to_owned()).collect::<Vec<_>>();
+        assert_eq!(ruby_files(&source), [Path::new("app/models/post.rb"), Path::new("app/models/user.rb")]);
+
+        std::fs::remove_file(root_path.join("app/models/post.rb")).unwrap();
+        write("app/services/signup/create.rb");
+        let changed_paths = [source.root().join("app/models/post.rb"), source.root().join("app/services")].into_iter().collect();
+        source.update(&changed_paths);
+        let updated = ruby_files(&source);
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        // new directories are walked as a whole
+        assert_eq!(updated, [Path::new("app/models/user.rb"), Path::new("app/services/signup/create.rb")]);
+    }
+
+    #[test]
+    fn test_walk_respects_ignore_files() {
+        let root_path = std::env::temp_dir().join(format!("packlite-ignore-test-{}", std::process::id()));
+        for (path, contents) in [
+            ("app/models/user.rb", ""),
+            ("tmp/generated.rb", ""),
+            ("legacy/old.rb", ""),
+            (".gitignore", "tmp/\n"),
+            (".packliteignore", "legacy\n"),
+        ] {
+            std::fs::create_dir_all(root_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root_path.join(path), contents).unwrap();
+        }
+
+        let ruby_files = |respect_ignore_files: bool| {
+            let walk_options = WalkOptions {
+                respect_ignore_files,
+                follow_symlinks: false,
+                exclude: Exclude::new(Vec::new()).unwrap(),
+            };
+            let source = Directory::new(&root_path, walk_options);
+            source.files().ruby_files.iter().map(|path| path.strip_prefix(source.root()).unwrap().to_owned()).collect::<Vec<_>>()
+        };
+        let (respected, ignored) = (ruby_files(true), ruby_files(false));
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        assert_eq!(respected, [Path::new("app/models/user.rb")]);
+        assert_eq!(ignored, [Path::new("app/models/user.rb"), Path::new("legacy/old.rb"), Path::new("tmp/generated.rb")]);
+    }
+
+    #[test]
+    fn test_walk_skips_unsafe_symlinks() {
+        let test_path = std::env::temp_dir().join(format!("packlite-symlink-test-{}", std::process::id()));
+        let root_path = test_path.join("project");
+        for path in ["project/app/models/user.rb", "outside/secret.rb"] {
+            std::fs::create_dir_all(test_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(test_path.join(path), "").unwrap();
+        }
+        for (link, target) in [
+            // a second path to a file of the project
+            ("app/alias.rb", "models/user.rb"),
+            ("lib", "app"),
+            // files and directories outside of the project
+            ("app/secret.rb", "../../outside/secret.rb"),
+            ("vendor", "../outside"),
+            ("app/broken.rb", "missing.rb"),
+            ("app/models/loop", ".."),
+        ] {
+            std::os::unix::fs::symlink(target, root_path.join(link)).unwrap();
+        }
+
+        let ruby_files = |follow_symlinks: bool| {
+            let walk_options = WalkOptions {
+                respect_ignore_files: false,
+                follow_symlinks,
+                exclude: Exclude::new(Vec::new()).unwrap(),
+            };
+            let source = Directory::new(&root_path, walk_options);
+            source.files().ruby_files.iter().map(|path| path.strip_prefix(source.root()).unwrap().to_owned()).collect::<Vec<_>>()
+        };
+        let (followed, skipped) = (ruby_files(true), ruby_files(false));
+        std::fs::remove_dir_all(&test_path).unwrap();
+
+        // every file is found once, under its real path, and nothing outside of the project is
+        assert_eq!(followed, [Path::new("app/models/user.rb")]);
+        assert_eq!(skipped, [Path::new("app/models/user.rb")]);
+    }
+
+    #[test]
+    fn test_package_paths() {
+        let package_yml = "enforce_dependencies: true\nenforce_privacy: true\n";
+        let source = InMemory::new(
+            Path::new("/project"),
+            [
+                "package.yml",
+                "packs/a/package.yml",
+                "packs/b/nested/package.yml",
+                "components/x/core/package.yml",
+                "gems/g/package.yml",
+            ]
+            .map(|path| (path, package_yml)),
+        );
+        let packs = |package_paths: &[&str]| {
+            let package_paths = package_paths.iter().map(|package_path| package_path.to_string()).collect::<Vec<_>>();
+            let mut packs = all_packages(&source, &package_paths).unwrap().iter().map(|package| package.name.to_string()).collect::<Vec<_>>();
+            packs.sort();
+            packs
+        };
+
+        assert_eq!(packs(&[]), ["components/x/core", "gems/g", "packs/a", "packs/b/nested", "root"]);
+        // `*` stays within a directory, `**` doesn't, and a leading `./` or trailing `/` doesn't matter
+        assert_eq!(packs(&["packs/*"]), ["packs/a", "root"]);
+        assert_eq!(packs(&["./packs/**/", "components/*/core"]), ["components/x/core", "packs/a", "packs/b/nested", "root"]);
+    }
 }
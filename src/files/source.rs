@@ -0,0 +1,271 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use super::{walkdir, FileKind, ProjectFiles, WalkOptions, IGNORE_FILE_NAME};
+
+/// Where the project's files come from. Paths handed out and accepted are absolute, under `root`.
+pub trait FileSource: Send + Sync {
+    /// canonical root directory of the project
+    fn root(&self) -> &Path;
+
+    fn files(&self) -> &ProjectFiles;
+
+    fn read(&self, path: &Path) -> io::Result<String>;
+
+    /// Calls `visit` on every ruby file, in parallel and in no particular order.
+    fn for_each_ruby_file(&self, visit: &(dyn Fn(&Path) + Sync)) {
+        self.files().ruby_files.par_iter().for_each(|path| visit(path));
+    }
+
+    /// Catches up with the files or directories at `changed_paths` being added, changed or removed. Sources that don't
+    /// follow the disk have nothing to do.
+    fn update(&mut self, _changed_paths: &HashSet<PathBuf>) {}
+}
+
+/// Files on disk, discovered by walking the root directory the first time they're needed.
+pub struct Directory {
+    root: PathBuf,
+    root_path: PathBuf,
+    walk_options: WalkOptions,
+    walk: OnceLock<Walk>,
+}
+
+/// What walking the root directory found.
+struct Walk {
+    files: ProjectFiles,
+    /// canonical paths of the directories the walk went into, including the root
+    directories: HashSet<PathBuf>,
+}
+
+impl Directory {
+    pub fn new(root_path: &Path, walk_options: WalkOptions) -> Self {
+        Self {
+            root: std::fs::canonicalize(root_path).unwrap(),
+            root_path: root_path.to_owned(),
+            walk_options,
+            walk: OnceLock::new(),
+        }
+    }
+
+    fn walk(&self, visit_ruby_file: &(dyn Fn(&Path) + Sync)) -> Walk {
+        let mut walk = Walk {
+            files: ProjectFiles {
+                ruby_files: Vec::new(),
+                package_files: Vec::new(),
+            },
+            directories: HashSet::from([self.root.clone()]),
+        };
+        walk.add(walkdir(&self.root_path, &self.walk_options, &self.root_path, None, visit_ruby_file));
+        walk
+    }
+
+    /// `directory` as seen from `root_path`, which the walk starts from.
+    fn walk_path(&self, directory: &Path) -> PathBuf {
+        self.root_path.join(directory.strip_prefix(&self.root).unwrap())
+    }
+}
+
+impl Walk {
+    /// Adds what a walk found, keeping the files sorted.
+    fn add(&mut self, found: Vec<(FileKind, PathBuf)>) {
+        for (kind, path) in found {
+            match kind {
+                FileKind::Ruby => self.files.ruby_files.push(path),
+                FileKind::Package => self.files.package_files.push(path),
+                FileKind::Directory => {
+                    self.directories.insert(path);
+                }
+            }
+        }
+
+        for files in [&mut self.files.ruby_files, &mut self.files.package_files] {
+            files.sort_unstable();
+            files.dedup();
+        }
+    }
+
+    /// Removes `path` and everything under it.
+    fn remove(&mut self, path: &Path) {
+        self.files.ruby_files.retain(|file| !file.starts_with(path));
+        self.files.package_files.retain(|file| !file.starts_with(path));
+        self.directories.retain(|directory| !directory.starts_with(path));
+    }
+}
+
+impl FileSource for Directory {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn files(&self) -> &ProjectFiles {
+        &self.walk.get_or_init(|| self.walk(&|_| {})).files
+    }
+
+    fn read(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    /// The first call visits files as the walk finds them, so reading and parsing them overlaps with walking.
+    fn for_each_ruby_file(&self, visit: &(dyn Fn(&Path) + Sync)) {
+        let mut walked = false;
+        let walk = self.walk.get_or_init(|| {
+            walked = true;
+            self.walk(visit)
+        });
+
+        if !walked {
+            walk.files.ruby_files.par_iter().for_each(|path| visit(path));
+        }
+    }
+
+    /// Only walks the directories the changes are in, one level deep, and the directories that appeared in them. A
+    /// changed ignore file can affect any directory below it, so everything is walked again the next time it's needed.
+    fn update(&mut self, changed_paths: &HashSet<PathBuf>) {
+        let is_ignore_file = |path: &Path| {
+            path.file_name()
+                .is_some_and(|file_name| [".gitignore", ".ignore", IGNORE_FILE_NAME].iter().any(|name| file_name == *name))
+        };
+        if self.walk_options.respect_ignore_files && changed_paths.iter().any(|path| is_ignore_file(path)) {
+            self.walk = OnceLock::new();
+            return;
+        }
+
+        let Some(mut walk) = self.walk.take() else {
+            return;
+        };
+
+        // changes in directories the walk skipped, e.g. ignored ones, don't matter
+        let directories: BTreeSet<&Path> = changed_paths
+            .iter()
+            .filter_map(|path| path.parent())
+            .filter(|directory| walk.directories.contains(*directory))
+            .collect();
+
+        for directory in directories {
+            let found = if directory.is_dir() {
+                walkdir(&self.root_path, &self.walk_options, &self.walk_path(directory), Some(1), &|_| {})
+            } else {
+                Vec::new()
+            };
+
+            for path in changed_paths.iter().filter(|path| path.parent() == Some(directory)) {
+                if !found.iter().any(|(_, found_path)| found_path == path) {
+                    walk.remove(path);
+                }
+            }
+
+            for (kind, path) in &found {
+                if *kind == FileKind::Directory && !walk.directories.contains(path) {
+                    walk.add(walkdir(&self.root_path, &self.walk_options, &self.walk_path(path), None, &|_| {}));
+                }
+            }
+            walk.add(found);
+        }
+
+        self.walk = OnceLock::from(walk);
+    }
+}
+
+/// Files that only exist in memory, e.g. for tests or unsaved editor buffers. The root doesn't have to exist.
+pub struct InMemory {
+    root: PathBuf,
+    files: ProjectFiles,
+    contents: HashMap<PathBuf, String>,
+}
+
+impl InMemory {
+    /// `files` are paths relative to `root_path` (absolute paths are taken as is) along with their contents.
+    pub fn new<P: AsRef<Path>, C: Into<String>>(root_path: &Path, files: impl IntoIterator<Item = (P, C)>) -> Self {
+        let contents: HashMap<PathBuf, String> = files.into_iter().map(|(path, contents)| (root_path.join(path), contents.into())).collect();
+
+        let mut ruby_files = Vec::new();
+        let mut package_files = Vec::new();
+
+        for path in contents.keys() {
+            match FileKind::of(path) {
+                Some(FileKind::Ruby) => ruby_files.push(path.clone()),
+                Some(FileKind::Package) => package_files.push(path.clone()),
+                Some(FileKind::Directory) | None => {}
+            }
+        }
+
+        ruby_files.sort();
+        package_files.sort();
+
+        Self {
+            root: root_path.to_owned(),
+            files: ProjectFiles { ruby_files, package_files },
+            contents,
+        }
+    }
+}
+
+impl FileSource for InMemory {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn files(&self) -> &ProjectFiles {
+        &self.files
+    }
+
+    fn read(&self, path: &Path) -> io::Result<String> {
+        self.contents.get(path).cloned().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not an in memory file"))
+    }
+}
+
+/// In memory files layered over another source, which they add to or shadow.
+pub struct Overlay<'a> {
+    base: Box<dyn FileSource + 'a>,
+    overrides: InMemory,
+    files: ProjectFiles,
+}
+
+impl<'a> Overlay<'a> {
+    pub fn new(base: Box<dyn FileSource + 'a>, overrides: InMemory) -> Self {
+        let files = merged_files(base.files(), &overrides.files);
+        Self { base, overrides, files }
+    }
+}
+
+fn merged_files(base: &ProjectFiles, overrides: &ProjectFiles) -> ProjectFiles {
+    let merge = |base: &[PathBuf], overrides: &[PathBuf]| {
+        let mut paths = [base, overrides].concat();
+        paths.sort();
+        paths.dedup();
+        paths
+    };
+
+    ProjectFiles {
+        ruby_files: merge(&base.ruby_files, &overrides.ruby_files),
+        package_files: merge(&base.package_files, &overrides.package_files),
+    }
+}
+
+impl<'a> FileSource for Overlay<'a> {
+    fn root(&self) -> &Path {
+        self.base.root()
+    }
+
+    fn files(&self) -> &ProjectFiles {
+        &self.files
+    }
+
+    fn read(&self, path: &Path) -> io::Result<String> {
+        match self.overrides.contents.get(path) {
+            Some(contents) => Ok(contents.clone()),
+            None => self.base.read(path),
+        }
+    }
+
+    fn update(&mut self, changed_paths: &HashSet<PathBuf>) {
+        self.base.update(changed_paths);
+        self.files = merged_files(self.base.files(), &self.overrides.files);
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::validator::ViolationType;
+
+/// How many of the packs before it each pack depends on.
+const DEPENDENCIES: usize = 5;
+
+/// Every fourth file of a pack is in its public path, starting with the first one.
+const PUBLIC_EVERY: usize = 4;
+
+const EXPECTED_VIOLATIONS_FILE_NAME: &str = "expected_violations.json";
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct Options {
+    #[clap(long, default_value = "50")]
+    pub packs: usize,
+
+    /// ruby files across all packs, each defining one class
+    #[clap(long, default_value = "1000")]
+    pub files: usize,
+
+    /// how many modules each class is nested in, counting the module of its pack
+    #[clap(long, default_value = "2")]
+    pub depth: usize,
+
+    /// constant references in each file
+    #[clap(long, default_value = "5")]
+    pub references_per_file: usize,
+
+    /// share of the references that go to other packs, the rest stay within the pack
+    #[clap(long, default_value = "0.3")]
+    pub cross_pack_rate: f64,
+
+    /// share of the references to other packs that are violations, either of privacy or of dependencies
+    #[clap(long, default_value = "0.1")]
+    pub violation_rate: f64,
+
+    /// the same seed and options always generate the same project
+    #[clap(long, default_value = "0")]
+    pub seed: u64,
+}
+
+/// A generated project, along with the violations checking it has to report.
+pub struct Project {
+    /// paths relative to the project root
+    pub files: Vec<(PathBuf, String)>,
+    pub expected_violations: Vec<ExpectedViolation>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExpectedViolation {
+    pub violation_type: ViolationType,
+    pub violating_pack: String,
+    pub violated_pack: String,
+    /// qualified name of the referenced constant
+    pub constant: String,
+    /// relative to the project root
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+struct Pack {
+    name: String,
+    module: String,
+    /// qualified class names, by file index
+    classes: Vec<String>,
+    /// indices of the packs this one depends on
+    dependencies: Vec<usize>,
+}
+
+fn is_public(file: usize) -> bool {
+    file.is_multiple_of(PUBLIC_EVERY)
+}
+
+/// Writes a project generated with `options` to `output`, which must not exist yet or be empty.
+pub fn generate(options: &Options, output: &Path) {
+    if options.packs == 0 || options.files < options.packs || options.depth == 0 {
+        eprintln!("expected at least one pack, at least as many files as packs, and a depth of at least one");
+        std::process::exit(1);
+    }
+    if output.read_dir().is_ok_and(|mut entries| entries.next().is_some()) {
+        eprintln!("{} is not empty", output.display());
+        std::process::exit(1);
+    }
+
+    let project = generate_project(options);
+
+    for (path, contents) in &project.files {
+        let path = output.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap_or_else(|error| panic!("failed to write {}: {}", path.display(), error));
+    }
+    std::fs::write(output.join(EXPECTED_VIOLATIONS_FILE_NAME), serde_json::to_string_pretty(&project.expected_violations).unwrap()).unwrap();
+
+    eprintln!(
+        "generated {} packs and {} ruby files in {}, {} lists the {} violations to expect",
+        options.packs,
+        options.files,
+        output.display(),
+        EXPECTED_VIOLATIONS_FILE_NAME,
+        project.expected_violations.len()
+    );
+}
+
+pub fn generate_project(options: &Options) -> Project {
+    let mut random = Random::new(options.seed);
+
+    let packs = (0..options.packs)
+        .map(|index| {
+            let module = format!("Pack{:04}", index);
+            let file_count = options.files / options.packs + usize::from(index < options.files % options.packs);
+
+            Pack {
+                name: format!("packs/pack_{:04}", index),
+                classes: (0..file_count).map(|file| namespace(&module, file, options.depth).join("::")).collect(),
+                module,
+                dependencies: (index.saturating_sub(DEPENDENCIES)..index).collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut files = vec![(PathBuf::from("package.yml"), "enforce_dependencies: false\nenforce_privacy: false\n".to_owned())];
+    let mut expected_violations = Vec::new();
+
+    for (index, pack) in packs.iter().enumerate() {
+        let dependencies = pack.dependencies.iter().map(|dependency| format!("  - {}\n", packs[*dependency].name)).collect::<String>();
+        files.push((
+            Path::new(&pack.name).join("package.yml"),
+            format!(
+                "enforce_dependencies: true\nenforce_privacy: true\ndependencies:{}\n{}",
+                if dependencies.is_empty() { " []" } else { "" },
+                dependencies
+            ),
+        ));
+
+        for file in 0..pack.classes.len() {
+            let namespace = namespace(&pack.module, file, options.depth);
+            let directory = if is_public(file) { "app/public" } else { "app/models" };
+            let path = Path::new(&pack.name)
+                .join(directory)
+                .join(namespace[1..].iter().map(|segment| snake_case(segment)).collect::<PathBuf>().with_extension("rb"));
+
+            let mut contents = String::new();
+            for (depth, segment) in namespace.iter().enumerate() {
+                let keyword = if depth == namespace.len() - 1 { "class" } else { "module" };
+                writeln!(contents, "{}{} {}", "  ".repeat(depth), keyword, segment).unwrap();
+            }
+            let indentation = "  ".repeat(namespace.len());
+            writeln!(contents, "{}def call", indentation).unwrap();
+
+            for reference_index in 0..options.references_per_file {
+                // after the modules, the class and the method
+                let line = namespace.len() + 1 + reference_index + 1;
+                let reference = reference(&packs, index, options, &mut random);
+                let referenced_pack = &packs[reference.pack];
+
+                // references within a pack are relative to its module, the others are resolved from the top level
+                let name = if reference.pack == index {
+                    &referenced_pack.classes[reference.file][pack.module.len() + 2..]
+                } else {
+                    &referenced_pack.classes[reference.file]
+                };
+                writeln!(contents, "{}  {}", indentation, name).unwrap();
+
+                if let Some(violation_type) = reference.violation_type {
+                    expected_violations.push(ExpectedViolation {
+                        violation_type,
+                        violating_pack: pack.name.clone(),
+                        violated_pack: referenced_pack.name.clone(),
+                        constant: referenced_pack.classes[reference.file].clone(),
+                        path: path.clone(),
+                        line,
+                    });
+                }
+            }
+
+            writeln!(contents, "{}end", indentation).unwrap();
+            for depth in (0..namespace.len()).rev() {
+                writeln!(contents, "{}end", "  ".repeat(depth)).unwrap();
+            }
+
+            files.push((path, contents));
+        }
+    }
+
+    expected_violations.sort();
+    Project { files, expected_violations }
+}
+
+/// The modules a class is nested in, followed by the class.
+fn namespace(module: &str, file: usize, depth: usize) -> Vec<String> {
+    let mut namespace = vec![module.to_owned()];
+    namespace.extend((1..depth).map(|level| format!("Group{}", (file / 3usize.pow(level as u32 - 1)) % 3)));
+    namespace.push(format!("Model{:04}", file));
+    namespace
+}
+
+fn snake_case(segment: &str) -> String {
+    let mut snake_case = String::new();
+    for (index, character) in segment.char_indices() {
+        if character.is_ascii_uppercase() && index > 0 {
+            snake_case.push('_');
+        }
+        snake_case.push(character.to_ascii_lowercase());
+    }
+    snake_case
+}
+
+struct Reference {
+    pack: usize,
+    file: usize,
+    violation_type: Option<ViolationType>,
+}
+
+/// Picks what a file of the pack at `index` references. When the violation that was rolled isn't possible, e.g.
+/// because every other pack is a dependency, the reference is allowed instead.
+fn reference(packs: &[Pack], index: usize, options: &Options, random: &mut Random) -> Reference {
+    let same_pack = Reference {
+        pack: index,
+        file: random.below(packs[index].classes.len()),
+        violation_type: None,
+    };
+    if !random.chance(options.cross_pack_rate) {
+        return same_pack;
+    }
+
+    let dependencies = &packs[index].dependencies;
+    let public_file = |random: &mut Random, pack: usize| random.below(packs[pack].classes.len().div_ceil(PUBLIC_EVERY)) * PUBLIC_EVERY;
+
+    if random.chance(options.violation_rate) {
+        let private_dependencies = dependencies.iter().copied().filter(|dependency| packs[*dependency].classes.len() > 1).collect::<Vec<_>>();
+        let undeclared_packs = (0..packs.len()).filter(|pack| *pack != index && !dependencies.contains(pack)).collect::<Vec<_>>();
+
+        if !private_dependencies.is_empty() && (undeclared_packs.is_empty() || random.chance(0.5)) {
+            let pack = private_dependencies[random.below(private_dependencies.len())];
+            let private_files = (0..packs[pack].classes.len()).filter(|file| !is_public(*file)).collect::<Vec<_>>();
+
+            return Reference {
+                pack,
+                file: private_files[random.below(private_files.len())],
+                violation_type: Some(ViolationType::Privacy),
+            };
+        }
+
+        if !undeclared_packs.is_empty() {
+            let pack = undeclared_packs[random.below(undeclared_packs.len())];
+
+            return Reference {
+                pack,
+                file: public_file(random, pack),
+                violation_type: Some(ViolationType::Dependency),
+            };
+        }
+    }
+
+    if dependencies.is_empty() {
+        return same_pack;
+    }
+
+    let pack = dependencies[random.below(dependencies.len())];
+    Reference {
+        pack,
+        file: public_file(random, pack),
+        violation_type: None,
+    }
+}
+
+/// splitmix64, so projects only depend on the seed and not on a random number crate's algorithm.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use itertools::Itertools;
+
+    use super::{generate_project, ExpectedViolation, Options};
+    use crate::{
+        files::{self, InMemory},
+        parser, resolver, validator,
+    };
+
+    #[test]
+    fn test_generated_violations_are_reported() {
+        let options = Options {
+            packs: 12,
+            files: 100,
+            depth: 3,
+            references_per_file: 6,
+            cross_pack_rate: 0.5,
+            violation_rate: 0.3,
+            seed: 7,
+        };
+        let project = generate_project(&options);
+        assert!(project.expected_violations.iter().any(|violation| violation.violation_type == validator::ViolationType::Privacy));
+        assert!(project.expected_violations.iter().any(|violation| violation.violation_type == validator::ViolationType::Dependency));
+
+        let source = InMemory::new(Path::new("/project"), project.files.iter().map(|(path, contents)| (path, contents.as_str())));
+        let packages = files::all_packages(&source, &[]).unwrap();
+        let (definitions, references) = resolver::resolve_references(parser::parse_ruby_files(&source, None), &[], &resolver::Scope::all());
+        let parsed_project = parser::apply_package_metadata(definitions, references, packages, "app/public", &[]);
+        assert_eq!(parsed_project.references.len(), options.files * options.references_per_file);
+
+        let violations = validator::validate(&parsed_project, false, true)
+            .into_iter()
+            .map(|violation| ExpectedViolation {
+                violation_type: violation.violation_type,
+                violating_pack: violation.violating_pack.to_string(),
+                violated_pack: violation.violated_pack.to_string(),
+                constant: violation.definition.name.to_string(),
+                path: violation.reference.loc.relative_path().to_owned(),
+                line: violation.reference.loc.begin.line,
+            })
+            .sorted()
+            .collect_vec();
+
+        assert_eq!(violations, project.expected_violations);
+        assert_eq!(generate_project(&options).files, project.files);
+    }
+}
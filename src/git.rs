@@ -0,0 +1,303 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::Mutex,
+};
+
+use tracing::instrument;
+
+use crate::files::{self, FileSource, ProjectFiles};
+
+/// The ruby and package.yml files of a git revision, read straight from the object database so the
+/// working directory doesn't need to be checked out at that revision. Files are read when they're needed.
+pub struct GitTree {
+    root: PathBuf,
+    files: ProjectFiles,
+    object_ids: HashMap<PathBuf, String>,
+    cat_file: Mutex<CatFile>,
+}
+
+struct TreeEntry {
+    object_id: String,
+    path: PathBuf,
+}
+
+impl GitTree {
+    /// Reads every file under `root_path` as of `rev`. Paths are reported as if the revision was checked out at `root_path`.
+    #[instrument(skip_all)]
+    pub fn read(root_path: &Path, rev: &str, exclude: &files::Exclude) -> Result<Self, String> {
+        let absolute_root_path = canonicalize(root_path)?;
+        verify_commit(root_path, rev)?;
+
+        let mut ruby_files = Vec::new();
+        let mut package_files = Vec::new();
+        let mut object_ids = HashMap::new();
+
+        for entry in ls_tree(root_path, rev, &[])? {
+            let is_ruby = entry.path.extension().is_some_and(|extension| extension == "rb");
+            let is_package = entry.path.file_name().is_some_and(|file_name| file_name == "package.yml");
+
+            if !(is_ruby || is_package) || exclude.excludes(&entry.path) {
+                continue;
+            }
+
+            let absolute_path = absolute_root_path.join(&entry.path);
+
+            if is_ruby {
+                ruby_files.push(absolute_path.clone());
+            } else {
+                package_files.push(absolute_path.clone());
+            }
+
+            object_ids.insert(absolute_path, entry.object_id);
+        }
+
+        ruby_files.sort();
+        package_files.sort();
+
+        Ok(Self {
+            root: absolute_root_path,
+            files: ProjectFiles { ruby_files, package_files },
+            object_ids,
+            cat_file: Mutex::new(CatFile::spawn(root_path)?),
+        })
+    }
+}
+
+impl FileSource for GitTree {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn files(&self) -> &ProjectFiles {
+        &self.files
+    }
+
+    fn read(&self, path: &Path) -> io::Result<String> {
+        let object_id = self.object_ids.get(path).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not part of the git tree"))?;
+        self.cat_file.lock().unwrap().read(object_id)
+    }
+}
+
+/// Paths, relative to `root_path`, of the files under it that differ between `rev` and the working directory,
+/// including deleted and untracked files. A renamed file is reported under both of its paths.
+#[instrument(skip_all)]
+pub fn changed_files(root_path: &Path, rev: &str) -> Result<Vec<PathBuf>, String> {
+    verify_commit(root_path, rev)?;
+    let changed = git(root_path, &["diff", "--name-only", "--no-renames", "--relative", "-z", rev, "--"])?;
+    let untracked = git(root_path, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+
+    let mut paths: Vec<PathBuf> = split_nul(&changed).chain(split_nul(&untracked)).map(PathBuf::from).collect();
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// The contents of `relative_paths` as of `rev`, keyed by absolute path. Files that didn't exist at `rev` are left out.
+#[instrument(skip_all)]
+pub fn read_files(root_path: &Path, rev: &str, relative_paths: &[PathBuf]) -> Result<Vec<(PathBuf, String)>, String> {
+    if relative_paths.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let absolute_root_path = canonicalize(root_path)?;
+    verify_commit(root_path, rev)?;
+    let entries = ls_tree(root_path, rev, relative_paths)?;
+    let mut cat_file = CatFile::spawn(root_path)?;
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let contents = cat_file
+                .read(&entry.object_id)
+                .map_err(|error| format!("failed to read {} at {}: {}", entry.path.display(), rev, error))?;
+            Ok((absolute_root_path.join(entry.path), contents))
+        })
+        .collect()
+}
+
+/// Whether `root_path` is in a git repository where `rev` names a commit. False when git isn't installed either.
+pub fn has_commit(root_path: &Path, rev: &str) -> bool {
+    Command::new("git")
+        .args(["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+        .current_dir(root_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Fails with git's own error outside of a repository, and with a clearer one than git's when `rev` isn't a commit.
+fn verify_commit(root_path: &Path, rev: &str) -> Result<(), String> {
+    git(root_path, &["rev-parse", "--git-dir"])?;
+
+    if !has_commit(root_path, rev) {
+        return Err(format!("'{}' isn't a commit of the git repository at {}", rev, root_path.display()));
+    }
+    Ok(())
+}
+
+fn git(root_path: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(root_path)
+        .output()
+        .map_err(|error| format!("failed to run git: {}", error))?;
+
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(output.stdout)
+}
+
+fn canonicalize(root_path: &Path) -> Result<PathBuf, String> {
+    std::fs::canonicalize(root_path).map_err(|error| format!("failed to read {}: {}", root_path.display(), error))
+}
+
+fn split_nul(output: &[u8]) -> impl Iterator<Item = String> + '_ {
+    output
+        .split(|byte| *byte == b'\0')
+        .filter(|line| !line.is_empty())
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+}
+
+/// Lists the regular files (no symlinks or submodules) under `root_path` at `rev`, relative to `root_path`.
+/// When `pathspecs` are given only those files are listed.
+fn ls_tree(root_path: &Path, rev: &str, pathspecs: &[PathBuf]) -> Result<Vec<TreeEntry>, String> {
+    let mut args = vec!["ls-tree", "-r", "-z", rev, "--"];
+    args.extend(pathspecs.iter().map(|pathspec| pathspec.to_str().unwrap()));
+
+    Ok(split_nul(&git(root_path, &args)?)
+        .filter_map(|line| {
+            // <mode> SP <type> SP <object>\t<path>
+            let (info, path) = line.split_once('\t')?;
+            let mut info = info.split(' ');
+            let (mode, object_type, object_id) = (info.next()?, info.next()?, info.next()?);
+
+            if object_type != "blob" || mode == "120000" {
+                return None;
+            }
+
+            Some(TreeEntry {
+                object_id: object_id.to_owned(),
+                path: PathBuf::from(path),
+            })
+        })
+        .collect())
+}
+
+/// A `git cat-file --batch` process that blobs are read through one at a time, as they're asked for.
+struct CatFile {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl CatFile {
+    fn spawn(root_path: &Path) -> Result<Self, String> {
+        let mut child = Command::new("git")
+            .args(["cat-file", "--batch"])
+            .current_dir(root_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("failed to run git: {}", error))?;
+
+        Ok(Self {
+            stdin: child.stdin.take(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+        })
+    }
+
+    fn read(&mut self, object_id: &str) -> io::Result<String> {
+        let stdin = self.stdin.as_mut().unwrap();
+        writeln!(stdin, "{}", object_id)?;
+        stdin.flush()?;
+
+        // <object> SP <type> SP <size> LF <contents> LF, or <object> SP missing LF
+        let mut header = String::new();
+        self.stdout.read_line(&mut header)?;
+        let size: usize = header
+            .trim_end()
+            .rsplit(' ')
+            .next()
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("unexpected git cat-file output: '{}'", header.trim_end())))?;
+
+        let mut blob = vec![0; size + 1];
+        self.stdout.read_exact(&mut blob)?;
+        blob.pop();
+
+        Ok(String::from_utf8_lossy(&blob).into_owned())
+    }
+}
+
+impl Drop for CatFile {
+    fn drop(&mut self) {
+        // git exits once its input is closed
+        drop(self.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{changed_files, git, GitTree};
+    use crate::files::{Exclude, FileSource};
+
+    /// A git repository in a temporary directory with `files` committed.
+    fn repository(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root_path = std::env::temp_dir().join(format!("packlite-{}-test-{}", name, std::process::id()));
+        for (path, contents) in files {
+            std::fs::create_dir_all(root_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root_path.join(path), contents).unwrap();
+        }
+        let git = |args: &[&str]| git(&root_path, args).unwrap();
+        git(&["init", "--quiet"]);
+        git(&["add", "."]);
+        git(&["-c", "user.name=packlite", "-c", "user.email=packlite@example.com", "commit", "--quiet", "-m", "initial"]);
+        std::fs::canonicalize(root_path).unwrap()
+    }
+
+    #[test]
+    fn test_git_tree() {
+        let root_path = repository("git-tree", &[("package.yml", ""), ("app/models/user.rb", "class User; end\n")]);
+        std::fs::write(root_path.join("app/models/user.rb"), "class Admin; end\n").unwrap();
+        std::fs::write(root_path.join("app/models/post.rb"), "class Post; end\n").unwrap();
+
+        let exclude = Exclude::new(Vec::new()).unwrap();
+        let tree = GitTree::read(&root_path, "HEAD", &exclude).unwrap();
+        let ruby_files = tree.files().ruby_files.iter().map(|path| path.strip_prefix(&root_path).unwrap().to_owned()).collect::<Vec<_>>();
+        let contents = tree.read(&root_path.join("app/models/user.rb")).unwrap();
+        let missing = tree.read(&root_path.join("app/models/post.rb")).is_err();
+        let bad_rev = GitTree::read(&root_path, "no-such-rev", &exclude).err();
+        drop(tree);
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        // the committed files, not the working directory
+        assert_eq!(ruby_files, [Path::new("app/models/user.rb")]);
+        assert_eq!(contents, "class User; end\n");
+        assert!(missing);
+        assert_eq!(bad_rev, Some(format!("'no-such-rev' isn't a commit of the git repository at {}", root_path.display())));
+    }
+
+    #[test]
+    fn test_changed_files_with_renames() {
+        let root_path = repository(
+            "renames",
+            &[("package.yml", ""), ("app/models/user.rb", "class User; end\n"), ("app/models/post.rb", "class Post; end\n")],
+        );
+        git(&root_path, &["mv", "app/models/user.rb", "app/models/account.rb"]).unwrap();
+        let changed = changed_files(&root_path, "HEAD").unwrap();
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        // the old path still defined the constant, so it's as much a change as the new one
+        assert_eq!(changed, [Path::new("app/models/account.rb"), Path::new("app/models/user.rb")]);
+    }
+}
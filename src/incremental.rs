@@ -0,0 +1,649 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    ast::{self, CaretPos, Constant, Loc},
+    cache::{self, CachedConstant, ParseCache},
+    files::{FileSource, Metadata, Package},
+    intern::{PathSymbol, Symbol},
+    parser::{self, Definition, Project, Reference},
+    resolver,
+    validator::{self, Violation, ViolationType},
+};
+
+/// Bumped whenever the saved state changes shape. It's read before the rest of the state, so a state saved in another
+/// format is started over instead of being misread.
+const FORMAT: u32 = 1;
+
+/// The resolved project, kept between runs. A change only resolves again the references whose candidates gained or
+/// lost their last definition, and only validates again the packs whose references or referenced constants it touched.
+/// The result is the same as checking the whole project from scratch.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Engine {
+    files: BTreeMap<PathBuf, FileState>,
+    /// the definitions of every file
+    namespace: resolver::Namespace,
+    /// the files with a reference that could resolve to each qualified name. Not saved, as it's larger than the
+    /// references it's worked out from
+    #[serde(skip)]
+    files_by_candidate: HashMap<String, HashSet<PathBuf>>,
+    /// fingerprint of the settings the owners of the files and the violations were worked out with
+    settings: String,
+    /// violations by violating pack, from the last validation. None until every pack was validated with the settings
+    violations_by_pack: Option<HashMap<Symbol, PackViolations>>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PackViolations {
+    violations: Vec<StoredViolation>,
+    /// suppressed by an ignore list, still needed to warn about ignores that aren't
+    ignored_violations: Vec<StoredViolation>,
+}
+
+/// A violation without the metadata of its packs, which comes from the packages again.
+#[derive(Serialize, Deserialize)]
+struct StoredViolation {
+    violation_type: ViolationType,
+    violated_pack: Symbol,
+    violating_pack: Symbol,
+    definition: Definition,
+    reference: Reference,
+    strict: bool,
+}
+
+/// Locations are stored without their paths, the path of the file is the key it's stored under.
+#[derive(Serialize, Deserialize)]
+struct FileState {
+    hash: String,
+    /// from another application, only used to resolve references
+    external: bool,
+    /// root of the application the file is in
+    root_path: PathSymbol,
+    definitions: Vec<CachedConstant>,
+    references: Vec<CachedConstant>,
+    resolved: Vec<ResolvedPosition>,
+    /// None for external files, and until the settings are known
+    owner: Option<Owner>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResolvedPosition {
+    name: Symbol,
+    begin: CaretPos,
+    end: CaretPos,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct Owner {
+    package: Symbol,
+    /// whether the file is in the public path of the package
+    public: bool,
+}
+
+pub enum FileChange {
+    /// the new contents of a file, either added or changed
+    Parsed {
+        file: ast::ParsedFile,
+        hash: String,
+        external: bool,
+        root_path: PathSymbol,
+    },
+    Removed(PathBuf),
+}
+
+impl FileChange {
+    fn path(&self) -> &Path {
+        match self {
+            FileChange::Parsed { file, .. } => &file.path,
+            FileChange::Removed(path) => path,
+        }
+    }
+}
+
+/// Everything besides the files that the project and its violations depend on.
+pub struct Settings<'a> {
+    pub packages: &'a [Package],
+    pub public_path: &'a str,
+    pub ignore_constants: &'a [String],
+    pub enforce_root: bool,
+}
+
+impl Settings<'_> {
+    fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_string(self.packages).unwrap());
+        hasher.update(serde_json::to_string(&(self.public_path, self.ignore_constants, self.enforce_root)).unwrap());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+impl Engine {
+    /// The state saved at `path` by an earlier run, or an empty one.
+    #[instrument(skip_all)]
+    pub fn load(path: &Path) -> Self {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(_) => return Self::default(),
+        };
+
+        Self::from_bytes(&bytes).unwrap_or_else(|error| {
+            warn!("ignoring unreadable incremental state {}: {}", path.display(), error);
+            Self::default()
+        })
+    }
+
+    fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        let format: u32 = bincode::deserialize(bytes)?;
+        if format != FORMAT {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!("saved in format {}, not {}", format, FORMAT))));
+        }
+        let (_, mut engine): (u32, Self) = bincode::deserialize(bytes)?;
+
+        for (path, state) in &engine.files {
+            for candidate in state.references(path).flat_map(|reference| resolver::candidates(&reference)) {
+                engine.files_by_candidate.entry(candidate).or_default().insert(path.clone());
+            }
+        }
+        Ok(engine)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&(FORMAT, self)).unwrap()
+    }
+
+    /// Failing to save only costs a full run next time, so errors are logged rather than fatal.
+    #[instrument(skip_all)]
+    pub fn save(&self, path: &Path) {
+        if let Err(error) = cache::write_atomically(path, &self.to_bytes()) {
+            warn!("failed to save incremental state {}: {}", path.display(), error);
+        }
+    }
+
+    /// The qualified names defined by every file, including those of the external sources and ignored constants, as
+    /// references are resolved against it.
+    pub fn namespace(&self) -> &resolver::Namespace {
+        &self.namespace
+    }
+
+    /// Brings the state up to date with the files of `source`, and of the `external_sources` its references may resolve into.
+    #[instrument(skip_all)]
+    pub fn refresh(
+        &mut self,
+        source: &dyn FileSource,
+        external_sources: &[&dyn FileSource],
+        changed_paths: Option<&HashSet<PathBuf>>,
+        parse_cache: Option<&ParseCache>,
+        settings: &Settings,
+    ) -> (Project, Vec<Violation>) {
+        let changes = self.changes(source, external_sources, changed_paths, parse_cache);
+        self.update(changes, settings)
+    }
+
+    /// How the files of `source` and `external_sources` differ from the state, sorted by path. Files are compared by
+    /// content hash, only those that changed are parsed again. When it's known which files may have changed, e.g. from
+    /// filesystem notifications, passing them as `changed_paths` saves reading every other file. Files that can't be
+    /// read, e.g. because they were removed since they were listed, count as removed.
+    #[instrument(skip_all)]
+    pub fn changes(&self, source: &dyn FileSource, external_sources: &[&dyn FileSource], changed_paths: Option<&HashSet<PathBuf>>, parse_cache: Option<&ParseCache>) -> Vec<FileChange> {
+        let changes = Mutex::new(Vec::new());
+        let unreadable = Mutex::new(HashSet::new());
+        let mut present = HashSet::new();
+
+        for (source, external) in std::iter::once((source, false)).chain(external_sources.iter().map(|source| (*source, true))) {
+            let root_path = PathSymbol::intern(source.root());
+            source.for_each_ruby_file(&|path| {
+                if changed_paths.is_some_and(|changed_paths| !changed_paths.contains(path) && self.files.contains_key(path)) {
+                    return;
+                }
+
+                let text = match source.read(path) {
+                    Ok(text) => text,
+                    Err(error) => {
+                        warn!("skipping {}: {}", path.display(), error);
+                        unreadable.lock().unwrap().insert(path.to_owned());
+                        return;
+                    }
+                };
+
+                let hash = cache::content_hash(&text);
+                if self.files.get(path).is_some_and(|state| state.hash == hash && state.external == external) {
+                    return;
+                }
+
+                let file = match parse_cache {
+                    Some(parse_cache) => parse_cache.parse_text(&text, source.root(), path),
+                    None => ast::parse_ast_text(&text, source.root(), path),
+                };
+                changes.lock().unwrap().push(FileChange::Parsed { file, hash, external, root_path });
+            });
+            present.extend(source.files().ruby_files.iter().cloned());
+        }
+        for path in unreadable.into_inner().unwrap() {
+            present.remove(&path);
+        }
+
+        let mut changes = changes.into_inner().unwrap();
+        changes.extend(self.files.keys().filter(|path| !present.contains(*path)).cloned().map(FileChange::Removed));
+        changes.sort_unstable_by(|a, b| a.path().cmp(b.path()));
+        changes
+    }
+
+    /// Applies `changes` and returns the project along with its violations.
+    #[instrument(skip_all)]
+    pub fn update(&mut self, changes: Vec<FileChange>, settings: &Settings) -> (Project, Vec<Violation>) {
+        let (mut touched_paths, touched_names, unresolved_paths) = self.index(changes);
+
+        self.resolve(&unresolved_paths, &mut touched_paths);
+
+        let fingerprint = settings.fingerprint();
+        if self.settings != fingerprint {
+            self.settings = fingerprint;
+            self.violations_by_pack = None;
+            self.assign_owners(settings, None);
+        } else {
+            self.assign_owners(settings, Some(&touched_paths));
+        }
+
+        let project = self.project(settings);
+        let violations = self.validate(&project, settings, &touched_paths, &touched_names);
+
+        (project, violations)
+    }
+
+    /// Applies `changes` to the files and the namespace. Returns the paths and the qualified names the changes touched,
+    /// and the paths whose references may now resolve differently.
+    #[instrument(skip_all)]
+    fn index(&mut self, changes: Vec<FileChange>) -> (HashSet<PathBuf>, HashSet<String>, HashSet<PathBuf>) {
+        debug!("applying {} file changes", changes.len());
+
+        let mut touched_paths = HashSet::new();
+        // qualified names defined in a changed file, before or after the change
+        let mut touched_names = HashSet::new();
+        // whether each of the touched names was defined before the changes
+        let mut defined_before = HashMap::new();
+
+        for change in changes {
+            let (path, state) = match change {
+                FileChange::Parsed { file, hash, external, root_path } => (
+                    file.path,
+                    Some(FileState {
+                        hash,
+                        external,
+                        root_path,
+                        definitions: file.definitions.iter().map(CachedConstant::new).collect(),
+                        // references from other applications are never resolved
+                        references: if external { Vec::new() } else { file.references.iter().map(CachedConstant::new).collect() },
+                        resolved: Vec::new(),
+                        owner: None,
+                    }),
+                ),
+                FileChange::Removed(path) => (path, None),
+            };
+
+            let old_state = self.files.remove(&path);
+            for definition in old_state.iter().chain(&state).flat_map(|state| state.definitions(&path)) {
+                let name = definition.qualified();
+                defined_before.entry(name.clone()).or_insert_with(|| self.namespace.is_defined(&name));
+                touched_names.insert(name);
+            }
+
+            if let Some(old_state) = old_state {
+                self.forget(&path, &old_state);
+            }
+            if let Some(state) = state {
+                self.remember(&path, &state);
+                self.files.insert(path.clone(), state);
+            }
+
+            touched_paths.insert(path);
+        }
+
+        // a reference resolves differently only if one of its candidates gained its first or lost its last definition
+        let mut unresolved_paths: HashSet<PathBuf> = touched_paths.iter().filter(|path| self.files.contains_key(*path)).cloned().collect();
+        for (name, defined_before) in defined_before {
+            if defined_before != self.namespace.is_defined(&name) {
+                unresolved_paths.extend(self.files_by_candidate.get(&name).into_iter().flatten().cloned());
+            }
+        }
+
+        (touched_paths, touched_names, unresolved_paths)
+    }
+
+    /// Resolves the references of `paths` again, adding the paths whose references now resolve differently to `touched_paths`.
+    #[instrument(skip_all)]
+    fn resolve(&mut self, paths: &HashSet<PathBuf>, touched_paths: &mut HashSet<PathBuf>) {
+        debug!("resolving the references of {} files", paths.len());
+        for path in paths {
+            let state = self.files.get_mut(path).unwrap();
+            let resolved = state
+                .references(path)
+                .filter_map(|reference| resolver::resolve_reference(&self.namespace, &reference))
+                .sorted_by_key(|reference| reference.loc.begin.line)
+                .map(|reference| ResolvedPosition {
+                    name: reference.name,
+                    begin: reference.loc.begin,
+                    end: reference.loc.end,
+                })
+                .collect_vec();
+
+            if state.resolved.iter().map(|reference| &reference.name).ne(resolved.iter().map(|reference| &reference.name)) {
+                touched_paths.insert(path.clone());
+            }
+            state.resolved = resolved;
+        }
+    }
+
+    fn remember(&mut self, path: &Path, state: &FileState) {
+        for definition in state.definitions(path) {
+            self.namespace.define(&definition.qualified());
+        }
+
+        for candidate in state.references(path).flat_map(|reference| resolver::candidates(&reference)) {
+            self.files_by_candidate.entry(candidate).or_default().insert(path.to_owned());
+        }
+    }
+
+    fn forget(&mut self, path: &Path, state: &FileState) {
+        for definition in state.definitions(path) {
+            self.namespace.undefine(&definition.qualified());
+        }
+
+        for candidate in state.references(path).flat_map(|reference| resolver::candidates(&reference)) {
+            if let Some(paths) = self.files_by_candidate.get_mut(&candidate) {
+                paths.remove(path);
+
+                if paths.is_empty() {
+                    self.files_by_candidate.remove(&candidate);
+                }
+            }
+        }
+    }
+
+    /// Works out the package of the files in `paths`, or of every file, the way `parser::apply_package_metadata` does.
+    #[instrument(skip_all)]
+    fn assign_owners(&mut self, settings: &Settings, paths: Option<&HashSet<PathBuf>>) {
+        let package_ownership = parser::PackageOwnership::new(settings.packages);
+
+        for (path, state) in &mut self.files {
+            if state.external || paths.is_some_and(|paths| !paths.contains(path)) {
+                continue;
+            }
+
+            let package = package_ownership.owner_of(path, path.strip_prefix(state.root_path.as_path()).unwrap());
+            state.owner = Some(Owner {
+                package: package.name,
+                // files a pack owns through `include` live outside of its public path
+                public: path.strip_prefix(&package.root).is_ok_and(|relative_path| relative_path.starts_with(settings.public_path)),
+            });
+        }
+    }
+
+    #[instrument(skip_all)]
+    fn project(&self, settings: &Settings) -> Project {
+        let mut definitions = Vec::new();
+        let mut references = Vec::new();
+
+        for (path, state) in self.files.iter().filter(|(_, state)| !state.external) {
+            let owner = state.owner.expect("files are assigned an owner before the project is built");
+
+            definitions.extend(
+                state
+                    .definitions(path)
+                    .filter(|definition| !settings.ignore_constants.iter().any(|ignored| definition.name == *ignored))
+                    .map(|definition| Definition {
+                        name: Symbol::intern(&definition.qualified()),
+                        loc: definition.loc,
+                        public: owner.public,
+                        package: owner.package,
+                    }),
+            );
+
+            let path = PathSymbol::intern(path);
+            references.extend(state.resolved.iter().map(|reference| Reference {
+                name: reference.name,
+                package: owner.package,
+                loc: Loc {
+                    path,
+                    root_path: state.root_path,
+                    begin: reference.begin,
+                    end: reference.end,
+                },
+            }));
+        }
+
+        Project {
+            packages: settings.packages.to_vec(),
+            definitions,
+            references,
+        }
+    }
+
+    /// Validates the packs with a reference in `touched_paths` or to one of `touched_names`, before or after the changes,
+    /// or every pack when the settings changed.
+    #[instrument(skip_all)]
+    fn validate(&mut self, project: &Project, settings: &Settings, touched_paths: &HashSet<PathBuf>, touched_names: &HashSet<String>) -> Vec<Violation> {
+        let validate_all = self.violations_by_pack.is_none();
+        let violations_by_pack = self.violations_by_pack.get_or_insert_with(HashMap::new);
+
+        let is_touched = |path: PathSymbol, name: Symbol| touched_paths.contains(path.as_path()) || touched_names.contains(name.as_str());
+        let affected_packs: HashSet<Symbol> = if validate_all {
+            project.packages.iter().map(|package| package.name).collect()
+        } else {
+            let previously_violating = violations_by_pack.iter().filter(|(_, pack_violations)| {
+                pack_violations
+                    .violations
+                    .iter()
+                    .chain(&pack_violations.ignored_violations)
+                    .any(|violation| is_touched(violation.reference.loc.path, violation.definition.name))
+            });
+
+            project
+                .references
+                .iter()
+                .filter(|reference| is_touched(reference.loc.path, reference.name))
+                .map(|reference| reference.package)
+                .chain(previously_violating.map(|(pack, _)| *pack))
+                .collect()
+        };
+        debug!("validating {} of {} packs", affected_packs.len(), project.packages.len());
+
+        let packages = project.packages.iter().filter(|package| affected_packs.contains(&package.name)).collect_vec();
+        let (violations, ignored_violations) = validator::validate_packages(project, &packages, settings.enforce_root);
+
+        violations_by_pack.retain(|pack, _| !affected_packs.contains(pack));
+        for package in packages {
+            violations_by_pack.insert(package.name, PackViolations::default());
+        }
+        for violation in violations {
+            violations_by_pack.get_mut(&violation.violating_pack).unwrap().violations.push(StoredViolation::new(violation));
+        }
+        for violation in ignored_violations {
+            violations_by_pack.get_mut(&violation.violating_pack).unwrap().ignored_violations.push(StoredViolation::new(violation));
+        }
+
+        let metadata_by_pack: HashMap<Symbol, &Metadata> = project.packages.iter().map(|package| (package.name, &package.metadata)).collect();
+        let ignored_violations = violations_by_pack
+            .values()
+            .flat_map(|pack_violations| pack_violations.ignored_violations.iter().map(|violation| violation.violation(&metadata_by_pack)))
+            .collect_vec();
+        validator::warn_about_configuration(project, &ignored_violations, true);
+
+        project
+            .packages
+            .iter()
+            .filter_map(|package| violations_by_pack.get(&package.name))
+            .flat_map(|pack_violations| pack_violations.violations.iter().map(|violation| violation.violation(&metadata_by_pack)))
+            .collect()
+    }
+}
+
+impl FileState {
+    /// The definitions of the file at `path`, which the state is stored under.
+    fn definitions<'a>(&'a self, path: &Path) -> impl Iterator<Item = Constant> + 'a {
+        let path = PathSymbol::intern(path);
+        self.definitions.iter().map(move |definition| definition.constant(path, self.root_path))
+    }
+
+    fn references<'a>(&'a self, path: &Path) -> impl Iterator<Item = Constant> + 'a {
+        let path = PathSymbol::intern(path);
+        self.references.iter().map(move |reference| reference.constant(path, self.root_path))
+    }
+}
+
+impl StoredViolation {
+    fn new(violation: Violation) -> Self {
+        Self {
+            violation_type: violation.violation_type,
+            violated_pack: violation.violated_pack,
+            violating_pack: violation.violating_pack,
+            definition: violation.definition,
+            reference: violation.reference,
+            strict: violation.strict,
+        }
+    }
+
+    fn violation(&self, metadata_by_pack: &HashMap<Symbol, &Metadata>) -> Violation {
+        Violation {
+            violation_type: self.violation_type.clone(),
+            violated_pack: self.violated_pack,
+            violated_pack_metadata: metadata_by_pack[&self.violated_pack].clone(),
+            violating_pack: self.violating_pack,
+            violating_pack_metadata: metadata_by_pack[&self.violating_pack].clone(),
+            definition: self.definition.clone(),
+            reference: self.reference.clone(),
+            strict: self.strict,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, path::Path};
+
+    use super::{Engine, Settings, FORMAT};
+    use crate::{
+        files::{self, InMemory},
+        intern, parser, resolver, validator,
+    };
+
+    fn full_run(source: &InMemory) -> Vec<String> {
+        let packages = files::all_packages(source, &[]).unwrap();
+        let (definitions, references) = resolver::resolve_references(parser::parse_ruby_files(source, None), &[], &resolver::Scope::all());
+        let project = parser::apply_package_metadata(definitions, references, packages, "app/public", &[]);
+
+        describe(&validator::validate(&project, false, true))
+    }
+
+    fn incremental_run(engine: &mut Engine, source: &InMemory) -> Vec<String> {
+        let packages = files::all_packages(source, &[]).unwrap();
+        let settings = Settings {
+            packages: &packages,
+            public_path: "app/public",
+            ignore_constants: &[],
+            enforce_root: false,
+        };
+
+        describe(&engine.refresh(source, &[], None, None, &settings).1)
+    }
+
+    fn describe(violations: &[validator::Violation]) -> Vec<String> {
+        violations
+            .iter()
+            .map(|violation| {
+                format!(
+                    "{} {} -> {} ::{} in {}",
+                    violation.violation_type,
+                    violation.violating_pack,
+                    violation.violated_pack,
+                    violation.definition.name,
+                    violation.reference.loc.relative_path().display()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_full_run() {
+        let mut files: BTreeMap<&str, &str> = [
+            ("package.yml", "enforce_dependencies: false\nenforce_privacy: false\n"),
+            ("packs/a/package.yml", "enforce_dependencies: true\nenforce_privacy: true\ndependencies: []\n"),
+            ("packs/a/app/models/a.rb", "module A\n  class Model\n    def run\n      Shared\n    end\n  end\nend\n"),
+            ("packs/b/package.yml", "enforce_dependencies: true\nenforce_privacy: true\n"),
+            ("packs/b/app/models/shared.rb", "class Shared\nend\n"),
+            ("packs/c/package.yml", "enforce_dependencies: true\nenforce_privacy: true\n"),
+            ("packs/c/app/models/c.rb", "class C\n  Shared\nend\n"),
+        ]
+        .into_iter()
+        .collect();
+
+        let changes = [
+            ("packs/b/app/models/shared.rb", Some("class Shared\nend\n")),
+            // A::Shared now shadows ::Shared for the reference in A::Model
+            ("packs/c/app/models/a_shared.rb", Some("module A\n  class Shared\n  end\nend\n")),
+            // the only definition of ::Shared is gone
+            ("packs/b/app/models/shared.rb", None),
+            // and comes back as part of the public api of b
+            ("packs/b/app/public/shared.rb", Some("class Shared\nend\n")),
+            ("packs/c/app/models/a_shared.rb", None),
+            ("packs/c/app/models/c.rb", Some("class C\nend\n")),
+            ("packs/a/package.yml", Some("enforce_dependencies: true\nenforce_privacy: true\ndependencies:\n  - packs/b\n")),
+        ];
+
+        let mut engine = Engine::default();
+        for (path, contents) in changes {
+            match contents {
+                Some(contents) => files.insert(path, contents),
+                None => files.remove(path),
+            };
+
+            let source = InMemory::new(Path::new("/project"), files.clone());
+            assert_eq!(incremental_run(&mut engine, &source), full_run(&source), "after changing {}", path);
+
+            // the next run starts from the saved state, like the next invocation would
+            engine = Engine::from_bytes(&engine.to_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_state_of_another_format() {
+        let bytes = bincode::serialize(&(FORMAT + 1, Engine::default())).unwrap();
+        assert!(Engine::from_bytes(&bytes).is_err_and(|error| error.to_string() == format!("saved in format {}, not {}", FORMAT + 1, FORMAT)));
+    }
+
+    #[test]
+    fn test_rechecking_interns_nothing_new() {
+        // other tests intern concurrently, so only what's unique to this project is counted
+        let source = InMemory::new(
+            Path::new("/interning"),
+            [
+                ("package.yml", "enforce_dependencies: false\nenforce_privacy: false\n"),
+                ("packs/interning_a/package.yml", "enforce_dependencies: true\nenforce_privacy: true\n"),
+                ("packs/interning_a/app/models/interning_a.rb", "module InterningA\n  class Model\n    InterningB\n  end\nend\n"),
+                ("packs/interning_b/package.yml", "enforce_dependencies: true\nenforce_privacy: true\n"),
+                ("packs/interning_b/app/models/interning_b.rb", "class InterningB\nend\n"),
+            ],
+        );
+        let interned = || intern::count_interned(|value| value.to_lowercase().contains("interning"));
+
+        let mut engine = Engine::default();
+        let violations = incremental_run(&mut engine, &source);
+        full_run(&source);
+        let first_check = interned();
+
+        for _ in 0..3 {
+            assert_eq!(incremental_run(&mut engine, &source), violations);
+            assert_eq!(incremental_run(&mut Engine::default(), &source), violations);
+            assert_eq!(full_run(&source), violations);
+        }
+        assert!(first_check > 0);
+        assert_eq!(interned(), first_check);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    path::Path,
+    sync::{LazyLock, RwLock},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An interned string, e.g. a constant or pack name. Equal strings share an id, so copying, comparing and hashing
+/// are cheap, and each distinct string is stored once for the whole run.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// An interned path, like [`Symbol`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PathSymbol(u32);
+
+static SYMBOLS: LazyLock<RwLock<Interner<str>>> = LazyLock::new(Default::default);
+static PATH_SYMBOLS: LazyLock<RwLock<Interner<Path>>> = LazyLock::new(Default::default);
+
+/// Interned values are leaked, they live as long as the process. Interning a value again reuses it, so memory is bounded
+/// by the distinct paths and names seen: checking unchanged files again, e.g. in `watch` or `serve`, interns nothing
+/// new, and only edits that bring new paths or names grow it.
+struct Interner<T: ?Sized + 'static> {
+    ids: HashMap<&'static T, u32>,
+    values: Vec<&'static T>,
+}
+
+impl<T: ?Sized> Default for Interner<T> {
+    fn default() -> Self {
+        Self {
+            ids: HashMap::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T: ?Sized + Hash + Eq> Interner<T> {
+    fn intern(interner: &RwLock<Self>, value: &T, leak: impl FnOnce(&T) -> &'static T) -> u32 {
+        if let Some(id) = interner.read().unwrap().ids.get(value) {
+            return *id;
+        }
+
+        let mut interner = interner.write().unwrap();
+        if let Some(id) = interner.ids.get(value) {
+            return *id;
+        }
+
+        let value = leak(value);
+        let id = u32::try_from(interner.values.len()).expect("too many interned values");
+        interner.values.push(value);
+        interner.ids.insert(value, id);
+        id
+    }
+
+    fn get(interner: &RwLock<Self>, id: u32) -> &'static T {
+        interner.read().unwrap().values[id as usize]
+    }
+}
+
+/// How many interned strings and paths `predicate` accepts, to tell in tests what a piece of work interned.
+#[cfg(test)]
+pub fn count_interned(predicate: impl Fn(&str) -> bool) -> usize {
+    let symbols = SYMBOLS.read().unwrap().values.iter().filter(|string| predicate(string)).count();
+    let paths = PATH_SYMBOLS.read().unwrap().values.iter().filter(|path| predicate(&path.to_string_lossy())).count();
+    symbols + paths
+}
+
+impl Symbol {
+    pub fn intern(string: &str) -> Self {
+        Self(Interner::intern(&SYMBOLS, string, |string| Box::leak(string.into())))
+    }
+
+    pub fn as_str(self) -> &'static str {
+        Interner::get(&SYMBOLS, self.0)
+    }
+}
+
+impl PathSymbol {
+    pub fn intern(path: &Path) -> Self {
+        Self(Interner::intern(&PATH_SYMBOLS, path, |path| Box::leak(path.into())))
+    }
+
+    pub fn as_path(self) -> &'static Path {
+        Interner::get(&PATH_SYMBOLS, self.0)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+/// Ordered by the interned string, not by the id, so sorting doesn't depend on the order strings were interned in.
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PathSymbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_path().cmp(other.as_path())
+    }
+}
+
+impl PartialOrd for PathSymbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Debug for PathSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_path(), f)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_str().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::intern(&String::deserialize(deserializer)?))
+    }
+}
+
+impl Serialize for PathSymbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_path().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PathSymbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::intern(&std::path::PathBuf::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{PathSymbol, Symbol};
+
+    #[test]
+    fn test_interning() {
+        assert_eq!(Symbol::intern("Foo::Bar"), Symbol::intern(&format!("{}::{}", "Foo", "Bar")));
+        assert_ne!(Symbol::intern("Foo"), Symbol::intern("Bar"));
+        assert_eq!(Symbol::intern("Foo::Bar").as_str(), "Foo::Bar");
+        assert!(Symbol::intern("Zebra") > Symbol::intern("Aardvark"));
+
+        let path = PathSymbol::intern(Path::new("/project/app/models/foo.rb"));
+        assert_eq!(path, PathSymbol::intern(&Path::new("/project").join("app/models/foo.rb")));
+        assert_eq!(path.as_path(), Path::new("/project/app/models/foo.rb"));
+    }
+}
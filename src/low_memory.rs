@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use itertools::Itertools;
+use tracing::{debug, instrument};
+
+use crate::{application_index, ast, cache, check_scope, exit_on_error, files, intern::Symbol, load_packages, parser, resolver, validator, Application, CheckTarget, CliCommand};
+
+/// Checks every application with only its definitions in memory. References are written to a temporary file as files
+/// get parsed, then read back, resolved and validated one pack at a time. The results are the same as checking in memory.
+#[instrument(skip_all)]
+pub fn check(
+    command: &CliCommand,
+    applications: &[Application],
+    sources: &[Box<dyn files::FileSource + '_>],
+    check_target: &CheckTarget,
+    parse_cache: Option<&cache::ParseCache>,
+) -> Vec<(parser::Project, Vec<validator::Violation>)> {
+    let ignored_constants = command.ignored_constants();
+
+    applications
+        .iter()
+        .zip(sources)
+        .map(|(application, source)| {
+            let source = source.as_ref();
+            let store = ReferenceStore::create();
+
+            // packages are loaded after parsing, so the files are parsed while the walk discovering them goes on
+            debug!("parsing ruby files, writing references to a temporary file");
+            let mut parsed_files = parse_ruby_files(source, parse_cache, |path, references| {
+                if !references.is_empty() {
+                    store.write(path, &references);
+                }
+            });
+            parsed_files.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+            let packages = exit_on_error(load_packages(command, source));
+            let mut records_by_pack: Vec<Vec<Record>> = packages.iter().map(|_| Vec::new()).collect();
+            {
+                let package_ownership = parser::PackageOwnership::new(&packages);
+                let pack_index: HashMap<Symbol, usize> = packages.iter().enumerate().map(|(index, package)| (package.name, index)).collect();
+
+                for record in store.records() {
+                    let owner = package_ownership.owner_of(&record.path, record.path.strip_prefix(source.root()).unwrap());
+                    records_by_pack[pack_index[&owner.name]].push(record);
+                }
+            }
+
+            let scope = check_scope(check_target, source, &parsed_files, parse_cache);
+            let external_definitions = application
+                .uses
+                .iter()
+                .flat_map(|used| parse_ruby_files(sources[application_index(applications, used)].as_ref(), parse_cache, |_, _| {}))
+                .flat_map(|parsed_file| parsed_file.definitions)
+                .collect_vec();
+
+            let definitions = parsed_files.into_iter().flat_map(|parsed_file| parsed_file.definitions).collect_vec();
+            let namespace = index_definitions(&definitions, &external_definitions);
+            drop(external_definitions);
+
+            let project = parser::apply_package_metadata(definitions, Vec::new(), packages, &command.public_path, &ignored_constants);
+            let pack_validator = validator::PackValidator::new(&project, application.config.enforce_root);
+            let mut violations = Vec::new();
+            let mut ignored_violations = Vec::new();
+
+            for (package, records) in project.packages.iter().zip(records_by_pack) {
+                let package_name = package.name;
+                let references = resolve_references(&store, records, &scope, &namespace)
+                    .into_iter()
+                    .map(|reference| parser::Reference {
+                        package: package_name,
+                        name: reference.name,
+                        loc: reference.loc,
+                    })
+                    .collect_vec();
+
+                let (mut package_violations, mut package_ignored_violations) = pack_validator.validate(package, &references.iter().collect_vec());
+                violations.append(&mut package_violations);
+                ignored_violations.append(&mut package_ignored_violations);
+            }
+
+            drop(pack_validator);
+            validator::warn_about_configuration(&project, &ignored_violations, check_target.is_everything());
+            (project, violations)
+        })
+        .collect()
+}
+
+#[instrument(skip_all)]
+fn index_definitions(definitions: &[ast::Constant], external_definitions: &[ast::Constant]) -> resolver::Namespace {
+    let qualified_names = definitions.iter().chain(external_definitions).map(ast::Constant::qualified).collect_vec();
+    resolver::Namespace::from_definitions(qualified_names.iter().map(String::as_str))
+}
+
+/// The references in `scope` of the files in `records`, resolved and sorted by path and line.
+#[instrument(skip_all)]
+fn resolve_references(store: &ReferenceStore, mut records: Vec<Record>, scope: &resolver::Scope, namespace: &resolver::Namespace) -> Vec<resolver::ResolvedReference> {
+    records.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+    store
+        .read(records)
+        .into_iter()
+        .flat_map(|(path, references)| {
+            references
+                .into_iter()
+                .filter(|reference| scope.includes(&path, reference))
+                .filter_map(|reference| resolver::resolve_reference(namespace, &reference))
+                .sorted_by_key(|reference| reference.loc.begin.line)
+        })
+        .collect()
+}
+
+/// Parses every ruby file of `source`, handing the references of each file to `spill` instead of returning them.
+#[instrument(skip_all)]
+fn parse_ruby_files(source: &dyn files::FileSource, parse_cache: Option<&cache::ParseCache>, spill: impl Fn(&Path, Vec<ast::Constant>) + Sync) -> Vec<ast::ParsedFile> {
+    let parsed_files = Mutex::new(Vec::new());
+    source.for_each_ruby_file(&|path| {
+        let mut parsed_file = match parse_cache {
+            Some(parse_cache) => parse_cache.parse(source, path),
+            None => ast::parse_ast(source, path),
+        };
+        spill(path, std::mem::take(&mut parsed_file.references));
+        parsed_files.lock().unwrap().push(parsed_file);
+    });
+
+    parsed_files.into_inner().unwrap()
+}
+
+/// References by file, in an anonymous temporary file. It has no path other processes could open, and the OS removes
+/// it once it's closed, even when packlite panics or is killed.
+struct ReferenceStore {
+    writer: Mutex<StoreWriter>,
+    /// reads at an offset, leaving the position the writer appends at alone
+    reader: File,
+}
+
+struct StoreWriter {
+    file: BufWriter<File>,
+    length: u64,
+    records: Vec<Record>,
+}
+
+/// Where the references of a file are in the store.
+struct Record {
+    path: PathBuf,
+    offset: u64,
+    length: u64,
+}
+
+impl ReferenceStore {
+    fn create() -> Self {
+        let file = tempfile::tempfile().unwrap_or_else(|error| panic!("failed to create a temporary file: {}", error));
+
+        Self {
+            reader: file.try_clone().unwrap_or_else(|error| panic!("failed to open the temporary file again: {}", error)),
+            writer: Mutex::new(StoreWriter {
+                file: BufWriter::new(file),
+                length: 0,
+                records: Vec::new(),
+            }),
+        }
+    }
+
+    fn write(&self, path: &Path, references: &[ast::Constant]) {
+        let bytes = bincode::serialize(references).unwrap();
+        let mut writer = self.writer.lock().unwrap();
+
+        writer.file.write_all(&bytes).unwrap_or_else(|error| panic!("failed to write references: {}", error));
+        let record = Record {
+            path: path.to_owned(),
+            offset: writer.length,
+            length: bytes.len() as u64,
+        };
+        writer.length += record.length;
+        writer.records.push(record);
+    }
+
+    /// Every file written so far, once the writes are flushed so the files can be read.
+    fn records(&self) -> Vec<Record> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.file.flush().unwrap_or_else(|error| panic!("failed to write references: {}", error));
+
+        std::mem::take(&mut writer.records)
+    }
+
+    /// The references of the files in `records`, in the same order.
+    fn read(&self, records: Vec<Record>) -> Vec<(PathBuf, Vec<ast::Constant>)> {
+        records
+            .into_iter()
+            .map(|record| {
+                let mut bytes = vec![0; record.length as usize];
+                self.reader
+                    .read_exact_at(&mut bytes, record.offset)
+                    .unwrap_or_else(|error| panic!("failed to read references: {}", error));
+
+                (record.path, bincode::deserialize(&bytes).unwrap())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use clap::Parser;
+
+    use crate::{
+        check_scoped, config,
+        files::{FileSource, InMemory},
+        generate, Application, CheckTarget, CliCommand,
+    };
+
+    #[test]
+    fn test_same_violations_as_in_memory() {
+        let options = generate::Options {
+            packs: 8,
+            files: 60,
+            depth: 2,
+            references_per_file: 5,
+            cross_pack_rate: 0.5,
+            violation_rate: 0.3,
+            seed: 11,
+        };
+        let project = generate::generate_project(&options);
+        let root_path = Path::new("/project");
+        let source = || -> Vec<Box<dyn FileSource>> { vec![Box::new(InMemory::new(root_path, project.files.iter().map(|(path, contents)| (path, contents.as_str()))))] };
+
+        let command = CliCommand::parse_from(["packlite", "--root-dir", "/project", "check"]);
+        let applications = [Application {
+            name: ".".to_owned(),
+            root_path: root_path.to_owned(),
+            config: config::Config::default(),
+            uses: Vec::new(),
+        }];
+
+        for check_target in [CheckTarget::Everything, CheckTarget::Paths(vec![PathBuf::from("/project/packs/pack_0003")])] {
+            let violations = |results: Vec<(_, Vec<_>)>| results.into_iter().map(|(_, violations)| serde_json::to_string(&violations).unwrap()).collect::<Vec<_>>();
+            let in_memory = violations(check_scoped(&command, &applications, &source(), &check_target, None));
+            let low_memory = violations(super::check(&command, &applications, &source(), &check_target, None));
+
+            assert_ne!(in_memory, ["[]"]);
+            assert_eq!(low_memory, in_memory);
+        }
+    }
+}
@@ -1,23 +1,107 @@
 mod ast;
+mod cache;
+mod config;
 mod files;
+mod generate;
+mod git;
+mod incremental;
+mod intern;
+mod low_memory;
 mod parser;
 mod resolver;
+mod serve;
+mod timings;
 mod validator;
-use std::path::PathBuf;
+mod watch;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use clap::Parser;
 use itertools::Itertools;
 use std::io::prelude::*;
 use tracing::{debug, instrument};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
 #[derive(clap::Args, Debug)]
 struct UpdateDeprecations {
     pack: Option<String>,
 }
 
+#[derive(clap::Args, Debug)]
+struct Check {
+    /// only report violations for references in these files or directories, definitions still come from the whole project
+    paths: Vec<PathBuf>,
+
+    /// only report violations for files changed since this git revision, and for references elsewhere that the changes could affect
+    #[clap(long, conflicts_with = "files-from")]
+    changed_since: Option<String>,
+
+    /// like `--changed-since`, with the changed files read from this file (`-` for stdin), one path per line. In a git
+    /// repository the files are compared with HEAD, so constants they no longer define count as well; elsewhere only
+    /// the constants they define now do
+    #[clap(long)]
+    files_from: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct Serve {
+    /// where to listen, relative to the root directory
+    #[clap(long, default_value = "tmp/packlite.sock")]
+    socket: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct Generate {
+    /// directory to write the project to, it must not exist yet or be empty
+    output: PathBuf,
+
+    #[clap(flatten)]
+    options: generate::Options,
+}
+
+/// The references `check` reports violations for.
+enum CheckTarget {
+    Everything,
+    /// references in these files or directories
+    Paths(Vec<PathBuf>),
+    /// references in changed files, plus references elsewhere whose resolution the changes could affect.
+    /// Knowing `base`, the revision the files changed since, lets constants that were removed count as well.
+    Changes {
+        files: Vec<PathBuf>,
+        base: Option<String>,
+    },
+}
+
+impl CheckTarget {
+    fn is_everything(&self) -> bool {
+        matches!(self, CheckTarget::Everything)
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum CacheCommand {
+    /// Remove the parse cache
+    Clear,
+}
+
 #[derive(clap::Subcommand, Debug)]
 enum Command {
     UpdateDeprecations(UpdateDeprecations),
+    /// Print all violations as json
+    Check(Check),
+    /// Keep checking the project as files change, printing violations as they appear (+) or get fixed (-)
+    Watch,
+    /// Answer JSON-RPC requests about the project on a Unix socket, keeping it up to date as files change
+    Serve(Serve),
+    /// Manage the parse cache
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
+    /// Generate a synthetic project for benchmarks and end-to-end tests, along with the violations it contains
+    Generate(Generate),
 }
 
 #[derive(Parser, Debug)]
@@ -34,13 +118,58 @@ struct CliCommand {
     #[clap(short, long, default_value = "app/public")]
     public_path: String,
 
-    /// paths to scan for packages
-    #[clap(long)]
-    package_paths: Vec<PathBuf>,
+    /// globs of the directories whose package.yml defines a pack, e.g. `packs/*` (defaults to every package.yml)
+    #[clap(long, validator = check_glob)]
+    package_paths: Vec<String>,
 
     /// constants that we should omit from reference resolution
     #[clap(short, long)]
     ignore_constants: Vec<String>,
+
+    /// only check packs whose package.yml metadata matches, e.g. `--metadata owner=payments`
+    #[clap(long)]
+    metadata: Vec<files::MetadataFilter>,
+
+    /// globs of files or directories to skip, on top of the `exclude` list in packlite.yml. The summary counts a skipped
+    /// directory as one entry
+    #[clap(long, validator = check_glob)]
+    exclude: Vec<String>,
+
+    /// don't skip files excluded by .gitignore, .git/info/exclude or .packliteignore
+    #[clap(long)]
+    no_ignore: bool,
+
+    /// analyze the project as of this git revision, reading files from the repository instead of the working directory
+    #[clap(long)]
+    rev: Option<String>,
+
+    /// read the contents of this file, relative to the root directory, from stdin instead of from disk (e.g. an unsaved editor buffer)
+    #[clap(long)]
+    stdin_buffer: Option<PathBuf>,
+
+    /// parse every file, without reading or writing the parse cache, even when packlite.yml turns it on
+    #[clap(long)]
+    no_cache: bool,
+
+    /// write the parsed project as json to this path
+    #[clap(long)]
+    dump_project: Option<PathBuf>,
+
+    /// keep references on disk instead of in memory, resolving and validating one pack at a time
+    #[clap(long, conflicts_with = "dump-project")]
+    low_memory: bool,
+
+    /// print how long walking and parsing, reading packages, resolution, metadata and validation took, and the slowest files to parse
+    #[clap(long)]
+    timings: bool,
+
+    /// how many of the slowest files to parse `--timings` prints
+    #[clap(long, default_value = "10")]
+    slowest_files: usize,
+
+    /// write a Chrome trace of the run to this path, to open in chrome://tracing or Perfetto
+    #[clap(long)]
+    trace_file: Option<PathBuf>,
 }
 
 impl CliCommand {
@@ -62,49 +191,418 @@ impl CliCommand {
 }
 
 fn main() {
-    install_logger();
-
     let command = CliCommand::parse();
-    do_run(command)
+    let report = install_logger(&command);
+
+    let failed = do_run(command);
+    if let Some(report) = report {
+        report.finish();
+    }
+    if failed {
+        std::process::exit(1);
+    }
 }
 
-fn install_logger() {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+/// Logs to stderr. With `--timings` or `--trace-file`, spans are also recorded, whatever the log level, to report on once the run is done.
+fn install_logger(command: &CliCommand) -> Option<timings::Report> {
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn"));
+    // only filters the log, so spans still reach the profiler
+    let log = tracing_subscriber::fmt::layer()
         .with_target(true)
         .with_timer(tracing_subscriber::fmt::time::uptime())
         .with_level(true)
         .with_writer(std::io::stderr)
-        .init();
+        .with_filter(env_filter);
+
+    let report = (command.timings || command.trace_file.is_some()).then(|| timings::Report {
+        profiler: timings::Profiler::new(),
+        timings: command.timings,
+        slowest_files: command.slowest_files,
+        trace_file: command.trace_file.clone(),
+    });
+    tracing_subscriber::registry().with(log).with(report.as_ref().map(|report| report.profiler.layer())).init();
+
+    report
 }
 
+/// A project root with its own packs and packlite.yml. Without `applications` in packlite.yml, the root directory is the only one.
+struct Application {
+    /// the root as listed in packlite.yml, relative to the root directory
+    name: String,
+    root_path: PathBuf,
+    config: config::Config,
+    /// names of the applications whose constants references in this one may resolve to, without being validated
+    uses: Vec<String>,
+}
+
+fn applications(command: &CliCommand, config: config::Config) -> Result<Vec<Application>, String> {
+    if config.applications.is_empty() {
+        return Ok(vec![Application {
+            name: ".".to_owned(),
+            root_path: command.root_dir.clone(),
+            config,
+            uses: Vec::new(),
+        }]);
+    }
+
+    let names = config.applications.iter().map(|application| application.root.clone()).collect_vec();
+
+    config
+        .applications
+        .into_iter()
+        .map(|application| {
+            if let Some(unknown) = application.uses.iter().find(|used| !names.contains(used)) {
+                return Err(format!(
+                    "application '{}' uses '{}', which isn't one of the applications in {}",
+                    application.root,
+                    unknown,
+                    config::CONFIG_FILE_NAME
+                ));
+            }
+
+            let root_path = command.root_dir.join(&application.root);
+            if !root_path.is_dir() {
+                return Err(format!("application '{}' in {} isn't a directory", application.root, config::CONFIG_FILE_NAME));
+            }
+
+            Ok(Application {
+                name: application.root,
+                config: config::load(&root_path, command.rev.as_deref())?,
+                root_path,
+                uses: application.uses,
+            })
+        })
+        .collect()
+}
+
+/// Reports an error the user has to fix, e.g. in a configuration file, and exits.
+fn exit_on_error<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    })
+}
+
+/// Returns whether the run failed, e.g. because there are violations.
 #[instrument(skip_all)]
-fn do_run(command: CliCommand) {
-    debug!("reading file paths");
-    let ruby_files = files::all_ruby_files(&command.root_dir, &command.package_paths);
-    let packages = files::all_packages(&command.root_dir, &command.package_paths);
-    debug!("found {} packages and {} ruby files", packages.len(), ruby_files.len());
+fn do_run(command: CliCommand) -> bool {
+    if let Command::Generate(cmd) = &command.command {
+        generate::generate(&cmd.options, &cmd.output);
+        return false;
+    }
 
-    debug!("parsing ruby files");
-    let parsed_files = parser::parse_ruby_files(&command.root_dir, &ruby_files);
+    let config = exit_on_error(config::load(&command.root_dir, command.rev.as_deref()));
+    let parse_cache = cache::ParseCache::new(&command.root_dir.join(&config.cache_directory));
+    if let Command::Cache { command: CacheCommand::Clear } = &command.command {
+        parse_cache.clear();
+        return false;
+    }
+    let parse_cache = (config.cache && !command.no_cache).then_some(parse_cache);
 
-    debug!("resolving references");
-    let (definitions, references) = resolver::resolve_references(parsed_files);
-    let project = parser::apply_package_metadata(definitions, references, packages, &command.public_path, &command.ignored_constants());
+    match &command.command {
+        Command::Watch => {
+            watch::watch(&command, parse_cache.as_ref());
+            return false;
+        }
+        Command::Serve(cmd) => {
+            serve::serve(&command, parse_cache.as_ref(), &command.root_dir.join(&cmd.socket));
+            return false;
+        }
+        _ => {}
+    }
+
+    let has_applications = !config.applications.is_empty();
+    let applications = exit_on_error(applications(&command, config));
+
+    let stdin_buffer = command.stdin_buffer.as_ref().map(|path| {
+        let mut contents = String::new();
+        std::io::stdin().read_to_string(&mut contents).unwrap();
+        (std::fs::canonicalize(&command.root_dir).unwrap().join(path), contents)
+    });
+
+    // directories are walked while their files are parsed, the first time the files are needed
+    let walk_options = applications.iter().map(|application| walk_options(&command, application)).collect_vec();
+    let sources = applications
+        .iter()
+        .zip(&walk_options)
+        .map(|(application, walk_options)| exit_on_error(file_source(&command, &application.root_path, walk_options, &stdin_buffer)))
+        .collect_vec();
+
+    let check_target = check_target(&command, &std::fs::canonicalize(&command.root_dir).unwrap());
+    let (projects, violations): (Vec<parser::Project>, Vec<Vec<validator::Violation>>) = match check_target {
+        _ if command.low_memory => low_memory::check(&command, &applications, &sources, &check_target, parse_cache.as_ref()),
+        CheckTarget::Everything => check_incrementally(&command, &applications, &sources, parse_cache.as_ref()),
+        _ => check_scoped(&command, &applications, &sources, &check_target, parse_cache.as_ref()),
+    }
+    .into_iter()
+    .unzip();
+
+    for (application, walk_options) in applications.iter().zip(&walk_options) {
+        let skipped_entries = walk_options.exclude.skipped_entries();
+        if !skipped_entries.is_empty() {
+            eprintln!(
+                "skipped {} excluded entries{} ({})",
+                skipped_entries.iter().map(|(_, count)| count).sum::<usize>(),
+                if has_applications { format!(" in {}", application.name) } else { String::new() },
+                skipped_entries.iter().map(|(pattern, count)| format!("{} by '{}'", count, pattern)).join(", ")
+            );
+        }
+    }
+
+    if let Some(dump_project) = &command.dump_project {
+        let json = if has_applications {
+            let project_by_application: BTreeMap<&str, &parser::Project> = applications.iter().map(|application| application.name.as_str()).zip(&projects).collect();
+            serde_json::to_string_pretty(&project_by_application).unwrap()
+        } else {
+            serde_json::to_string_pretty(&projects[0]).unwrap()
+        };
 
-    std::fs::File::create("/users/matan.zruya/desktop/output.json")
-        .unwrap()
-        .write_all(serde_json::to_string_pretty(&project).unwrap().as_bytes())
-        .unwrap();
+        std::fs::File::create(dump_project).unwrap().write_all(json.as_bytes()).unwrap();
+    }
+
+    let violations = projects
+        .iter()
+        .zip(violations)
+        .map(|(project, violations)| matching_metadata(&command, project, violations))
+        .collect_vec();
 
     debug!("running {:?}", command.command);
-    match command.command {
-        Command::UpdateDeprecations(_cmd) => {}
+    match &command.command {
+        Command::UpdateDeprecations(cmd) => {
+            let mut has_strict_violations = false;
+            for (project, violations) in projects.iter().zip(&violations) {
+                has_strict_violations |= update_deprecations(cmd, project, violations);
+            }
+
+            has_strict_violations
+        }
+        Command::Check(_) => {
+            if has_applications {
+                let violations_by_application: BTreeMap<&str, &Vec<validator::Violation>> = applications.iter().map(|application| application.name.as_str()).zip(&violations).collect();
+                check(&violations_by_application);
+                violations.iter().any(|violations| !violations.is_empty())
+            } else {
+                check(&violations[0]);
+                !violations[0].is_empty()
+            }
+        }
+        Command::Cache { .. } | Command::Watch | Command::Serve(_) | Command::Generate(_) => unreachable!("handled before checking the project"),
+    }
+}
+
+/// Checks every file of every application. With the cache enabled, the resolved project is kept between runs,
+/// so only what changed since the last run is parsed, resolved and validated again.
+fn check_incrementally(
+    command: &CliCommand,
+    applications: &[Application],
+    sources: &[Box<dyn files::FileSource + '_>],
+    parse_cache: Option<&cache::ParseCache>,
+) -> Vec<(parser::Project, Vec<validator::Violation>)> {
+    let ignored_constants = command.ignored_constants();
+
+    applications
+        .iter()
+        .zip(sources)
+        .map(|(application, source)| {
+            let state_path = parse_cache.map(|parse_cache| parse_cache.state_path(source.root(), command.rev.as_deref()));
+            let mut engine = state_path.as_deref().map(incremental::Engine::load).unwrap_or_default();
+
+            let external_sources = application.uses.iter().map(|used| sources[application_index(applications, used)].as_ref()).collect_vec();
+            let changes = engine.changes(source.as_ref(), &external_sources, None, parse_cache);
+            let packages = exit_on_error(load_packages(command, source.as_ref()));
+            let settings = incremental::Settings {
+                packages: &packages,
+                public_path: &command.public_path,
+                ignore_constants: &ignored_constants,
+                enforce_root: application.config.enforce_root,
+            };
+            let result = engine.update(changes, &settings);
+
+            if let Some(state_path) = state_path {
+                engine.save(&state_path);
+            }
+
+            result
+        })
+        .collect()
+}
+
+/// Checks the references in `check_target`, against the definitions of every file.
+fn check_scoped(
+    command: &CliCommand,
+    applications: &[Application],
+    sources: &[Box<dyn files::FileSource + '_>],
+    check_target: &CheckTarget,
+    parse_cache: Option<&cache::ParseCache>,
+) -> Vec<(parser::Project, Vec<validator::Violation>)> {
+    debug!("parsing ruby files");
+    let mut parsed_files = sources.iter().map(|source| parser::parse_ruby_files(source.as_ref(), parse_cache)).collect_vec();
+    let external_definitions = applications
+        .iter()
+        .map(|application| {
+            application
+                .uses
+                .iter()
+                .flat_map(|used| {
+                    parsed_files[application_index(applications, used)]
+                        .iter()
+                        .flat_map(|parsed_file| parsed_file.definitions.iter().cloned())
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+
+    sources
+        .iter()
+        .enumerate()
+        .map(|(index, source)| {
+            let packages = exit_on_error(load_packages(command, source.as_ref()));
+            let parsed_files = std::mem::take(&mut parsed_files[index]);
+            let project = build_project(command, sources[index].as_ref(), packages, parsed_files, &external_definitions[index], check_target, parse_cache);
+
+            debug!("validating");
+            let violations = validator::validate(&project, applications[index].config.enforce_root, check_target.is_everything());
+            (project, violations)
+        })
+        .collect()
+}
+
+fn application_index(applications: &[Application], name: &str) -> usize {
+    applications.iter().position(|application| application.name == name).unwrap()
+}
+
+/// The violations made by packs that match `--metadata`.
+fn matching_metadata(command: &CliCommand, project: &parser::Project, violations: Vec<validator::Violation>) -> Vec<validator::Violation> {
+    violations
+        .into_iter()
+        .filter(|violation| project.package(violation.violating_pack.as_str()).is_some_and(|package| package.matches_metadata(&command.metadata)))
+        .collect()
+}
+
+/// The files of `application`, along with the excludes applied to them.
+fn walk_options(command: &CliCommand, application: &Application) -> files::WalkOptions {
+    files::WalkOptions {
+        respect_ignore_files: !command.no_ignore,
+        follow_symlinks: application.config.follow_symlinks,
+        exclude: files::Exclude::new(application.config.exclude.iter().chain(&command.exclude).cloned().collect()).expect("exclude globs are checked when they're read"),
+    }
+}
+
+fn check_glob(glob: &str) -> Result<(), String> {
+    files::build_glob_set(&[glob.to_owned()]).map(|_| ())
+}
+
+/// Clones of `walk_options` share the counts of skipped entries, so the source counts into `walk_options` as well.
+fn file_source(command: &CliCommand, root_path: &Path, walk_options: &files::WalkOptions, stdin_buffer: &Option<(PathBuf, String)>) -> Result<Box<dyn files::FileSource>, String> {
+    let source: Box<dyn files::FileSource> = match &command.rev {
+        Some(rev) => Box::new(git::GitTree::read(root_path, rev, &walk_options.exclude)?),
+        None => Box::new(files::Directory::new(root_path, walk_options.clone())),
+    };
+
+    match stdin_buffer {
+        Some((path, contents)) if path.starts_with(source.root()) => {
+            let buffers = files::InMemory::new(source.root(), [(path, contents.clone())]);
+            Ok(Box::new(files::Overlay::new(source, buffers)))
+        }
+        _ => Ok(source),
     }
 }
 
-fn update_deprecations(command: &UpdateDeprecations, project: &parser::Project) {
-    let violations = validator::validate(project);
+fn check_scope(check_target: &CheckTarget, source: &dyn files::FileSource, parsed_files: &[ast::ParsedFile], parse_cache: Option<&cache::ParseCache>) -> resolver::Scope {
+    let defined_constants = |parsed_files: &[ast::ParsedFile], files: &[PathBuf]| -> Vec<String> {
+        parsed_files
+            .iter()
+            .filter(|parsed_file| files.contains(&parsed_file.path))
+            .flat_map(|parsed_file| parsed_file.definitions.iter().map(ast::Constant::qualified))
+            .collect()
+    };
+
+    match check_target {
+        CheckTarget::Everything => resolver::Scope::all(),
+        CheckTarget::Paths(paths) => resolver::Scope {
+            files: files::FileFilter::Only(paths.clone()),
+            constants: HashSet::new(),
+        },
+        CheckTarget::Changes { files, base } => {
+            let mut constants: HashSet<String> = defined_constants(parsed_files, files).into_iter().collect();
+
+            if let Some(base) = base {
+                let relative_paths = files.iter().filter_map(|path| path.strip_prefix(source.root()).ok()).map(Path::to_owned).collect_vec();
+                let base_source = files::InMemory::new(source.root(), exit_on_error(git::read_files(source.root(), base, &relative_paths)));
+                constants.extend(defined_constants(&parser::parse_ruby_files(&base_source, parse_cache), files));
+            }
+
+            resolver::Scope {
+                files: files::FileFilter::Only(files.clone()),
+                constants,
+            }
+        }
+    }
+}
+
+fn check_target(command: &CliCommand, root_path: &Path) -> CheckTarget {
+    let cmd = match &command.command {
+        Command::Check(cmd) => cmd,
+        _ => return CheckTarget::Everything,
+    };
+
+    let absolute_path = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| root_path.join(path));
+
+    if let Some(base) = &cmd.changed_since {
+        let files = exit_on_error(git::changed_files(root_path, base)).iter().map(|path| root_path.join(path)).collect();
+        CheckTarget::Changes { files, base: Some(base.clone()) }
+    } else if let Some(files_from) = &cmd.files_from {
+        let mut contents = String::new();
+        if files_from == Path::new("-") {
+            std::io::stdin().read_to_string(&mut contents).unwrap();
+        } else {
+            contents = std::fs::read_to_string(files_from).unwrap();
+        }
+
+        let files = contents.lines().filter(|line| !line.trim().is_empty()).map(|line| absolute_path(Path::new(line.trim()))).collect();
+        let base = git::has_commit(root_path, "HEAD").then(|| "HEAD".to_owned());
+        CheckTarget::Changes { files, base }
+    } else if !cmd.paths.is_empty() {
+        CheckTarget::Paths(cmd.paths.iter().map(|path| absolute_path(path)).collect())
+    } else {
+        CheckTarget::Everything
+    }
+}
+
+fn load_packages(command: &CliCommand, source: &dyn files::FileSource) -> Result<Vec<files::Package>, String> {
+    let packages = files::all_packages(source, &command.package_paths)?;
+    if !packages.iter().any(|package| package.name == files::ROOT_PACKAGE_NAME) {
+        return Err(format!("{} has no package.yml, it's needed to configure the root package", source.root().display()));
+    }
+    debug!("found {} packages and {} ruby files", packages.len(), source.files().ruby_files.len());
+
+    Ok(packages)
+}
+
+/// Definitions are indexed from every file in `source`, but only references in `check_target` are resolved.
+#[instrument(skip_all)]
+fn build_project(
+    command: &CliCommand,
+    source: &dyn files::FileSource,
+    packages: Vec<files::Package>,
+    parsed_files: Vec<ast::ParsedFile>,
+    external_definitions: &[ast::Constant],
+    check_target: &CheckTarget,
+    parse_cache: Option<&cache::ParseCache>,
+) -> parser::Project {
+    debug!("resolving references");
+    let scope = check_scope(check_target, source, &parsed_files, parse_cache);
+    let (definitions, references) = resolver::resolve_references(parsed_files, external_definitions, &scope);
+    parser::apply_package_metadata(definitions, references, packages, &command.public_path, &command.ignored_constants())
+}
+
+fn check(violations: &impl serde::Serialize) {
+    println!("{}", serde_json::to_string_pretty(violations).unwrap());
+}
+
+/// Returns whether there are strict violations, which can't be recorded.
+fn update_deprecations(command: &UpdateDeprecations, project: &parser::Project, violations: &[validator::Violation]) -> bool {
+    let (strict_violations, violations): (Vec<&validator::Violation>, Vec<&validator::Violation>) = violations.iter().partition(|violation| violation.strict);
     let mut deprecated_references = validator::deprecated_references(&violations);
 
     if let Some(pack) = &command.pack {
@@ -115,7 +613,7 @@ fn update_deprecations(command: &UpdateDeprecations, project: &parser::Project)
     }
 
     for deprecated_reference in deprecated_references {
-        let package = project.packages.iter().find(|package| package.name == deprecated_reference.violating_pack).unwrap();
+        let package = project.package(&deprecated_reference.violating_pack).unwrap();
         let deprecated_reference_file_path = package.root.join("deprecated_references.yml");
 
         std::fs::File::create(deprecated_reference_file_path)
@@ -123,4 +621,118 @@ fn update_deprecations(command: &UpdateDeprecations, project: &parser::Project)
             .write_all(serde_yaml::to_string(&deprecated_reference.deprecated_references).unwrap().as_bytes())
             .unwrap();
     }
+
+    for violation in &strict_violations {
+        eprintln!(
+            "{} violation: '{}' references '::{}' from strict pack '{}' in {}",
+            violation.violation_type,
+            violation.violating_pack,
+            violation.definition.name,
+            violation.violated_pack,
+            violation.reference.loc.relative_path().display()
+        );
+    }
+
+    !strict_violations.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Command};
+
+    use clap::Parser;
+
+    use crate::{check_scope, do_run, files::InMemory, parser, CheckTarget, CliCommand};
+
+    const FILES: [(&str, &str); 3] = [
+        ("packs/a/app/models/user.rb", "class User; end"),
+        ("packs/b/app/models/order.rb", "class Order; User; end"),
+        ("packs/b/app/models/invoice.rb", "class Invoice; Order; end"),
+    ];
+
+    #[test]
+    fn test_check_scope() {
+        let root_path = Path::new("/project");
+        let source = InMemory::new(root_path, FILES);
+        let parsed_files = parser::parse_ruby_files(&source, None);
+
+        let scope = check_scope(&CheckTarget::Paths(vec![root_path.join("packs/b")]), &source, &parsed_files, None);
+        assert!(scope.constants.is_empty());
+
+        let scope = check_scope(
+            &CheckTarget::Changes {
+                files: vec![root_path.join("packs/a/app/models/user.rb")],
+                base: None,
+            },
+            &source,
+            &parsed_files,
+            None,
+        );
+        assert_eq!(scope.constants.iter().collect::<Vec<_>>(), ["User"]);
+
+        // the changed file, and files referencing what it defines
+        let included = parsed_files
+            .iter()
+            .filter(|parsed_file| parsed_file.references.iter().any(|reference| scope.includes(&parsed_file.path, reference)))
+            .map(|parsed_file| parsed_file.path.strip_prefix(root_path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(included, [Path::new("packs/b/app/models/order.rb")]);
+    }
+
+    #[test]
+    fn test_check_scope_with_removed_definitions() {
+        let root_path = std::env::temp_dir().join(format!("packlite-scope-test-{}", std::process::id()));
+        for (path, contents) in FILES {
+            std::fs::create_dir_all(root_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root_path.join(path), contents).unwrap();
+        }
+        let git = |args: &[&str]| assert!(Command::new("git").args(args).current_dir(&root_path).output().unwrap().status.success());
+        git(&["init", "--quiet"]);
+        git(&["add", "."]);
+        git(&["-c", "user.name=packlite", "-c", "user.email=packlite@example.com", "commit", "--quiet", "-m", "initial"]);
+
+        // user.rb is deleted in the working directory, references to User still have to be checked
+        let source = InMemory::new(&root_path, FILES.into_iter().skip(1));
+        let parsed_files = parser::parse_ruby_files(&source, None);
+        let changes = |base: Option<&str>| CheckTarget::Changes {
+            files: vec![root_path.join("packs/a/app/models/user.rb")],
+            base: base.map(str::to_owned),
+        };
+
+        let without_base = check_scope(&changes(None), &source, &parsed_files, None);
+        let with_base = check_scope(&changes(Some("HEAD")), &source, &parsed_files, None);
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        assert!(without_base.constants.is_empty());
+        assert_eq!(with_base.constants.iter().collect::<Vec<_>>(), ["User"]);
+        assert!(with_base.files.contains(&root_path.join("packs/a/app/models/user.rb")));
+        assert!(!with_base.files.contains(&root_path.join("packs/b/app/models/order.rb")));
+    }
+
+    #[test]
+    fn test_update_deprecations_with_strict_packs() {
+        let root_path = std::env::temp_dir().join(format!("packlite-strict-test-{}", std::process::id()));
+        let files = [
+            ("package.yml", "enforce_dependencies: false\nenforce_privacy: false\n"),
+            ("packs/strict/package.yml", "enforce_dependencies: false\nenforce_privacy: strict\n"),
+            ("packs/strict/app/models/secret.rb", "class Secret; end"),
+            ("packs/lenient/package.yml", "enforce_dependencies: false\nenforce_privacy: true\n"),
+            ("packs/lenient/app/models/hidden.rb", "class Hidden; end"),
+            ("packs/user/package.yml", "enforce_dependencies: false\nenforce_privacy: false\n"),
+            ("packs/user/app/models/user.rb", "class User\n  def run\n    Secret\n    Hidden\n  end\nend\n"),
+        ];
+        for (path, contents) in files {
+            std::fs::create_dir_all(root_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root_path.join(path), contents).unwrap();
+        }
+
+        let failed = do_run(CliCommand::parse_from(["packlite", "--root-dir", root_path.to_str().unwrap(), "--no-cache", "update-deprecations"]));
+        let deprecated_references = std::fs::read_to_string(root_path.join("packs/user/deprecated_references.yml")).unwrap();
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        // the violation of the strict pack fails the run instead of being recorded
+        assert!(failed);
+        assert!(deprecated_references.contains("packs/lenient") && deprecated_references.contains("Hidden"));
+        assert!(!deprecated_references.contains("Secret"));
+    }
 }
@@ -1,29 +1,30 @@
-use std::{
-    collections::HashMap,
-    path::{Path, PathBuf},
-};
-
-use rayon::iter::{ParallelBridge, ParallelIterator};
-use serde::Serialize;
+use std::{collections::HashMap, path::Path, sync::Mutex};
 
+use globset::GlobSet;
+use itertools::Itertools;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
 
 use crate::{
     ast::{self, Loc},
-    files, resolver,
+    cache, files,
+    intern::{PathSymbol, Symbol},
+    resolver,
 };
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Definition {
-    pub package: String,
-    pub name: String,
+    pub package: Symbol,
+    pub name: Symbol,
     pub public: bool,
     pub loc: Loc,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reference {
-    pub package: String,
-    pub name: String,
+    pub package: Symbol,
+    pub name: Symbol,
     pub loc: Loc,
 }
 
@@ -34,32 +35,57 @@ pub struct Project {
     pub references: Vec<Reference>,
 }
 
-pub fn parse_ruby_files(root_path: &Path, ruby_files: &[PathBuf]) -> Vec<ast::ParsedFile> {
-    let root_path = std::fs::canonicalize(root_path).unwrap();
-    ruby_files.iter().par_bridge().map(|path| ast::parse_ast(&root_path, path)).collect()
+impl Project {
+    pub fn package(&self, name: &str) -> Option<&files::Package> {
+        self.packages.iter().find(|package| package.name == name)
+    }
+}
+
+/// Parses every ruby file of `source`, sorted by path.
+#[instrument(skip_all)]
+pub fn parse_ruby_files(source: &dyn files::FileSource, cache: Option<&cache::ParseCache>) -> Vec<ast::ParsedFile> {
+    let parsed_files = Mutex::new(Vec::new());
+    source.for_each_ruby_file(&|path| {
+        let parsed_file = match cache {
+            Some(cache) => cache.parse(source, path),
+            None => ast::parse_ast(source, path),
+        };
+        parsed_files.lock().unwrap().push(parsed_file);
+    });
+
+    let mut parsed_files = parsed_files.into_inner().unwrap();
+    parsed_files.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    parsed_files
 }
 
+#[instrument(skip_all)]
 pub fn apply_package_metadata(definitions: Vec<ast::Constant>, references: Vec<resolver::ResolvedReference>, packages: Vec<files::Package>, public_path: &str, ignore_constants: &[String]) -> Project {
-    let package_name_by_path: HashMap<&Path, &str> = packages.iter().map(|package| (package.root.as_ref(), package.name.as_ref())).collect();
+    let package_ownership = PackageOwnership::new(&packages);
+
+    let mut owner_by_path: HashMap<PathSymbol, (&files::Package, Symbol)> = HashMap::new();
+    for loc in definitions.iter().map(|definition| &definition.loc).chain(references.iter().map(|reference| &reference.loc)) {
+        owner_by_path.entry(loc.path).or_insert_with(|| {
+            let package = package_ownership.owner(loc);
+            (package, package.name)
+        });
+    }
 
     let definitions = definitions
-        .into_iter()
-        .par_bridge()
-        .filter(|definition| !ignore_constants.contains(&definition.name))
+        .into_par_iter()
+        .filter(|definition| !ignore_constants.iter().any(|ignored| definition.name == *ignored))
         .map(|definition| {
-            let package = find_package(&definition.loc.path, &package_name_by_path);
-
-            let mut public = false;
-            let mut package_name = "root".to_string();
+            let (package, package_name) = owner_by_path[&definition.loc.path];
 
-            if let Some((path, name)) = package {
-                let relative_path = definition.loc.path.strip_prefix(path).unwrap();
-                public = relative_path.starts_with(public_path);
-                package_name = name;
-            }
+            // files a pack owns through `include` live outside of its public path
+            let public = definition
+                .loc
+                .path
+                .as_path()
+                .strip_prefix(&package.root)
+                .is_ok_and(|relative_path| relative_path.starts_with(public_path));
 
             Definition {
-                name: definition.qualified(),
+                name: Symbol::intern(&definition.qualified()),
                 loc: definition.loc,
                 public,
                 package: package_name,
@@ -68,15 +94,9 @@ pub fn apply_package_metadata(definitions: Vec<ast::Constant>, references: Vec<r
         .collect();
 
     let references = references
-        .into_iter()
-        .par_bridge()
+        .into_par_iter()
         .map(|reference| {
-            let package = find_package(&reference.loc.path, &package_name_by_path);
-            let mut package_name = "root".to_string();
-
-            if let Some((_path, name)) = package {
-                package_name = name;
-            }
+            let (_, package_name) = owner_by_path[&reference.loc.path];
 
             Reference {
                 name: reference.name,
@@ -89,7 +109,105 @@ pub fn apply_package_metadata(definitions: Vec<ast::Constant>, references: Vec<r
     Project { packages, definitions, references }
 }
 
-pub fn find_package<'a>(path: &'a Path, package_name_by_path: &'a HashMap<&'a Path, &'a str>) -> Option<(&'a Path, String)> {
-    let package = path.ancestors().filter_map(|ancestor| package_name_by_path.get_key_value(&ancestor)).next();
-    package.map(|(path, name)| (*path, name.to_string()))
+/// Decides which package owns a file. A pack that `include`s the file wins over the directory layout,
+/// otherwise the file belongs to the nearest ancestor package that doesn't `exclude` it, falling back to the root package.
+pub struct PackageOwnership<'a> {
+    root_package: &'a files::Package,
+    package_by_root: HashMap<&'a Path, &'a files::Package>,
+    include_by_package: Vec<(&'a files::Package, GlobSet)>,
+    exclude_by_package: HashMap<Symbol, GlobSet>,
+}
+
+impl<'a> PackageOwnership<'a> {
+    pub fn new(packages: &'a [files::Package]) -> Self {
+        Self {
+            root_package: packages.iter().find(|package| package.name == files::ROOT_PACKAGE_NAME).expect("the root directory has no package.yml"),
+            package_by_root: packages.iter().map(|package| (package.root.as_ref(), package)).collect(),
+            include_by_package: packages
+                .iter()
+                .filter(|package| !package.include.is_empty())
+                .map(|package| (package, files::package_glob_set(&package.include)))
+                .collect(),
+            exclude_by_package: packages
+                .iter()
+                .filter(|package| !package.exclude.is_empty())
+                .map(|package| (package.name, files::package_glob_set(&package.exclude)))
+                .collect(),
+        }
+    }
+
+    fn owner(&self, loc: &Loc) -> &'a files::Package {
+        self.owner_of(loc.path.as_path(), loc.relative_path())
+    }
+
+    /// `path` is absolute, `relative_path` the same path relative to the project root.
+    pub fn owner_of(&self, path: &Path, relative_path: &Path) -> &'a files::Package {
+        let claims = self
+            .include_by_package
+            .iter()
+            .filter(|(package, include)| include.is_match(relative_path) && !self.excludes(package, relative_path))
+            .map(|(package, _)| *package)
+            .collect_vec();
+
+        match claims.as_slice() {
+            [] => {}
+            [package] => return package,
+            _ => warn!(
+                "'{}' is included by more than one pack ({}), falling back to the pack of its directory",
+                relative_path.display(),
+                claims.iter().map(|package| &package.name).sorted().join(", ")
+            ),
+        }
+
+        path.ancestors()
+            .filter_map(|ancestor| self.package_by_root.get(ancestor))
+            .find(|package| !self.excludes(package, relative_path))
+            .copied()
+            .unwrap_or(self.root_package)
+    }
+
+    fn excludes(&self, package: &files::Package, relative_path: &Path) -> bool {
+        self.exclude_by_package.get(&package.name).is_some_and(|exclude| exclude.is_match(relative_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::PackageOwnership;
+    use crate::files::{self, InMemory};
+
+    #[test]
+    fn test_owner_of() {
+        let source = InMemory::new(
+            Path::new("/project"),
+            [
+                ("package.yml", "enforce_dependencies: false\nenforce_privacy: false\n"),
+                (
+                    "packs/a/package.yml",
+                    "enforce_dependencies: true\nenforce_privacy: true\ninclude:\n  - lib/a/**/*.rb\n  - lib/shared/*.rb\nexclude:\n  - packs/a/app/legacy/**\n",
+                ),
+                ("packs/b/package.yml", "enforce_dependencies: true\nenforce_privacy: true\ninclude:\n  - lib/shared/*.rb\n"),
+                (
+                    "packs/b/nested/package.yml",
+                    "enforce_dependencies: true\nenforce_privacy: true\nexclude:\n  - packs/b/nested/app/old.rb\n",
+                ),
+            ],
+        );
+        let packages = files::all_packages(&source, &[]).unwrap();
+        let ownership = PackageOwnership::new(&packages);
+        let owner = |relative_path: &str| ownership.owner_of(&Path::new("/project").join(relative_path), Path::new(relative_path)).name.as_str();
+
+        assert_eq!(owner("packs/a/app/models/a.rb"), "packs/a");
+        // included from outside the pack's directory
+        assert_eq!(owner("lib/a/deep/thing.rb"), "packs/a");
+        // excluded from the directory pack, so the next package up owns it
+        assert_eq!(owner("packs/a/app/legacy/old.rb"), "root");
+        assert_eq!(owner("packs/b/nested/app/old.rb"), "packs/b");
+        assert_eq!(owner("packs/b/nested/app/new.rb"), "packs/b/nested");
+        // claimed by two packs, so the directory decides
+        assert_eq!(owner("lib/shared/both.rb"), "root");
+        assert_eq!(owner("lib/other.rb"), "root");
+    }
 }
@@ -1,27 +1,139 @@
 mod ast_resolver;
+mod namespace;
 
-use crate::ast::{self, Loc};
-use serde::Serialize;
+pub use ast_resolver::{candidates, resolve_reference};
+pub use namespace::Namespace;
 
+use std::{collections::HashSet, path::Path};
 
-#[derive(Debug, Clone, Serialize)]
+use crate::{
+    ast::{self, Loc},
+    files::FileFilter,
+    intern::Symbol,
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResolvedReference {
-    pub name: String,
+    pub name: Symbol,
     pub loc: Loc,
 }
 
+/// The references to resolve: every reference in `files`, plus references in other files that could
+/// resolve to one of `constants` (qualified names), e.g. because the definition of that constant changed.
+pub struct Scope {
+    pub files: FileFilter,
+    pub constants: HashSet<String>,
+}
+
+impl Scope {
+    pub fn all() -> Self {
+        Self {
+            files: FileFilter::All,
+            constants: HashSet::new(),
+        }
+    }
+
+    /// Whether `reference`, made in the file at `path`, is one to resolve.
+    pub fn includes(&self, path: &Path, reference: &ast::Constant) -> bool {
+        self.files.contains(path) || (!self.constants.is_empty() && ast_resolver::candidates(reference).iter().any(|candidate| self.constants.contains(candidate)))
+    }
+}
 
-pub fn resolve_references(parsed_files: Vec<ast::ParsedFile>) -> (Vec<ast::Constant>, Vec<ResolvedReference>) {
+/// Definitions are collected from every parsed file, references only from the files and constants in `scope`.
+/// References may also resolve to `external_definitions`, e.g. from another application, which aren't returned.
+#[instrument(skip_all)]
+pub fn resolve_references(parsed_files: Vec<ast::ParsedFile>, external_definitions: &[ast::Constant], scope: &Scope) -> (Vec<ast::Constant>, Vec<ResolvedReference>) {
     let mut definitions: Vec<ast::Constant> = Vec::new();
     let mut references: Vec<ast::Constant> = Vec::new();
 
     for mut parsed_file in parsed_files {
         definitions.append(&mut parsed_file.definitions);
-        references.append(&mut parsed_file.references);
+
+        if scope.files.contains(&parsed_file.path) {
+            references.append(&mut parsed_file.references);
+        } else if !scope.constants.is_empty() {
+            references.extend(parsed_file.references.into_iter().filter(|reference| scope.includes(&parsed_file.path, reference)));
+        }
     }
 
+    let own_definitions = definitions.len();
+    definitions.extend_from_slice(external_definitions);
+
     // Resolves ruby constant references to the fully qualified constant they refer to.
     let references = ast_resolver::resolve(&definitions, &references);
+    definitions.truncate(own_definitions);
 
     (definitions, references)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        files::{self, Directory, Exclude, InMemory, WalkOptions},
+        parser, validator,
+    };
+
+    #[test]
+    fn test_external_definitions() {
+        let store = InMemory::new(Path::new("/apps/store"), [("app/models/admin/order.rb", "module Admin\n  class Order\n  end\nend\n")]);
+        let admin = InMemory::new(Path::new("/apps/admin"), [("app/models/admin/panel.rb", "module Admin\n  class Panel\n    Order\n  end\nend\n")]);
+        let external_definitions = parser::parse_ruby_files(&store, None).into_iter().flat_map(|parsed_file| parsed_file.definitions).collect::<Vec<_>>();
+
+        let (_, references) = super::resolve_references(parser::parse_ruby_files(&admin, None), &[], &super::Scope::all());
+        assert!(references.iter().all(|reference| reference.name != "Admin::Order"));
+
+        let (definitions, references) = super::resolve_references(parser::parse_ruby_files(&admin, None), &external_definitions, &super::Scope::all());
+        assert!(definitions.iter().all(|definition| definition.qualified() != "Admin::Order"));
+        assert!(references.iter().any(|reference| reference.name == "Admin::Order"));
+    }
+
+    #[test]
+    fn test_deterministic_output() {
+        let root_path = std::env::temp_dir().join(format!("packlite-deterministic-test-{}", std::process::id()));
+        let mut files = vec![("package.yml".to_owned(), "enforce_dependencies: false\nenforce_privacy: false\n".to_owned())];
+        for pack in ["a", "b", "c"] {
+            files.push((format!("packs/{}/package.yml", pack), "enforce_dependencies: true\nenforce_privacy: true\n".to_owned()));
+            for index in 0..20 {
+                // every file references the others on the same lines, so files only stay apart when sorted by path first
+                let references = ["a", "b", "c"].map(|other| format!("  {}{}\n", other.to_uppercase(), (index + 1) % 20)).concat();
+                files.push((
+                    format!("packs/{}/app/models/{}{}.rb", pack, pack, index),
+                    format!("class {}{}\n{}end\n", pack.to_uppercase(), index, references),
+                ));
+            }
+        }
+        for (path, contents) in &files {
+            std::fs::create_dir_all(root_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root_path.join(path), contents).unwrap();
+        }
+
+        let check = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| {
+                let walk_options = WalkOptions {
+                    respect_ignore_files: false,
+                    follow_symlinks: false,
+                    exclude: Exclude::new(Vec::new()).unwrap(),
+                };
+                // parsing starts while the directory is walked
+                let source = Directory::new(&root_path, walk_options);
+                let (definitions, references) = super::resolve_references(parser::parse_ruby_files(&source, None), &[], &super::Scope::all());
+                let project = parser::apply_package_metadata(definitions, references, files::all_packages(&source, &[]).unwrap(), "app/public", &[]);
+                // thread counts only change the order on machines with cores to spare, so the order is checked as well
+                assert!(project.definitions.is_sorted_by_key(|definition| definition.loc.path));
+                assert!(project.references.is_sorted_by_key(|reference| (reference.loc.path, reference.loc.begin.line)));
+                // the project too, as `--dump-project` prints it as it is
+                serde_json::to_string(&(validator::validate(&project, false, true), &project)).unwrap()
+            })
+        };
+        let outputs = [1, 2, 8, 8].map(check);
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        assert!(outputs[0].contains("privacy"));
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+    }
+}
@@ -1,46 +1,42 @@
-use std::collections::HashMap;
-
 use itertools::Itertools;
-use rayon::iter::{ParallelBridge, ParallelIterator};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use super::ResolvedReference;
+use super::{Namespace, ResolvedReference};
 use crate::ast::Constant;
 
 pub fn resolve(definitions: &[Constant], references: &[Constant]) -> Vec<ResolvedReference> {
-    let definition_by_qualified_name = definitions.iter().into_group_map_by(|definition| definition.qualified());
+    let qualified_names = definitions.iter().map(Constant::qualified).collect_vec();
+    let namespace = Namespace::from_definitions(qualified_names.iter().map(String::as_str));
 
-    let resolved_references: Vec<ResolvedReference> = references
-        .iter()
-        .par_bridge()
-        .filter_map(|reference| resolve_reference(&definition_by_qualified_name, reference))
-        .collect();
+    let resolved_references: Vec<ResolvedReference> = references.par_iter().filter_map(|reference| resolve_reference(&namespace, reference)).collect();
 
-    resolved_references.into_iter().sorted_by_key(|reference| reference.loc.begin.line).collect()
+    // stable, so references on the same line keep the order they were made in
+    resolved_references.into_iter().sorted_by_key(|reference| (reference.loc.path, reference.loc.begin.line)).collect()
 }
 
-fn resolve_reference(definition_by_qualified_name: &HashMap<String, Vec<&Constant>>, reference: &Constant) -> Option<ResolvedReference> {
-    let name = &reference.name;
-
-    if name.starts_with("::") {
-        let qualified_name = name.trim_start_matches("::");
+/// Resolves to the most nested candidate that's defined in `namespace`.
+pub fn resolve_reference(namespace: &Namespace, reference: &Constant) -> Option<ResolvedReference> {
+    namespace.resolve(reference).map(|name| ResolvedReference { name, loc: reference.loc })
+}
 
-        definition_by_qualified_name.get(qualified_name).map(|_| ResolvedReference {
-            name: qualified_name.to_owned(),
-            loc: reference.loc.clone(),
-        })
+/// The qualified names a reference could resolve to, most nested first.
+pub fn candidates(reference: &Constant) -> Vec<String> {
+    if reference.name.as_str().starts_with("::") {
+        vec![reference.name.as_str().trim_start_matches("::").to_owned()]
     } else {
-        let name = reference.nestings().into_iter().find(|nesting| definition_by_qualified_name.contains_key(nesting));
-
-        name.map(|name| ResolvedReference { name, loc: reference.loc.clone() })
+        reference.nestings()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::files::InMemory;
     use std::path::Path;
 
     fn test_fixture(ruby_file_path: &str, expectation_file_path: &str) {
-        let parsed_file = crate::ast::parse_ast(Path::new("./"), Path::new(ruby_file_path));
+        let text = std::fs::read_to_string(ruby_file_path).unwrap();
+        let source = InMemory::new(Path::new("./"), [(ruby_file_path, text)]);
+        let parsed_file = crate::ast::parse_ast(&source, Path::new(ruby_file_path));
         let actual = format!("{:#?}", super::resolve(&parsed_file.definitions, &parsed_file.references));
 
         if std::env::var("OVERWRITE_FIXTURES").is_ok() {
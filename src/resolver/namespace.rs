@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{ast::Constant, intern::Symbol};
+
+/// The constant namespace as a tree, with a node for each segment of a qualified name, e.g. `Foo` and `Foo::Bar`
+/// for `Foo::Bar`. References are resolved by walking the tree segment by segment, without building candidate names.
+#[derive(Serialize, Deserialize)]
+pub struct Namespace {
+    /// the first node is the top level namespace
+    nodes: Vec<Node>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Node {
+    /// the qualified name, empty for the top level namespace
+    name: Symbol,
+    /// the enclosing namespace, the top level namespace is its own parent
+    parent: usize,
+    children: HashMap<Box<str>, usize>,
+    /// how many times the name is defined, a node without definitions only exists as a namespace of others
+    definitions: usize,
+}
+
+const TOP_LEVEL: usize = 0;
+
+impl Default for Namespace {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node {
+                name: Symbol::intern(""),
+                parent: TOP_LEVEL,
+                children: HashMap::new(),
+                definitions: 0,
+            }],
+        }
+    }
+}
+
+impl Namespace {
+    pub fn from_definitions<'a>(qualified_names: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut namespace = Self::default();
+        for qualified_name in qualified_names {
+            namespace.define(qualified_name);
+        }
+        namespace
+    }
+
+    /// Adds a definition of `qualified_name`, e.g. `Foo::Bar`.
+    pub fn define(&mut self, qualified_name: &str) {
+        let mut node = TOP_LEVEL;
+
+        for segment in qualified_name.split("::") {
+            node = match self.nodes[node].children.get(segment) {
+                Some(&child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    let name = if node == TOP_LEVEL {
+                        segment.to_owned()
+                    } else {
+                        format!("{}::{}", self.nodes[node].name, segment)
+                    };
+
+                    self.nodes.push(Node {
+                        name: Symbol::intern(&name),
+                        parent: node,
+                        children: HashMap::new(),
+                        definitions: 0,
+                    });
+                    self.nodes[node].children.insert(segment.into(), child);
+                    child
+                }
+            };
+        }
+
+        self.nodes[node].definitions += 1;
+    }
+
+    /// Removes a definition added by `define`.
+    pub fn undefine(&mut self, qualified_name: &str) {
+        let node = self.find(TOP_LEVEL, qualified_name).unwrap_or_else(|| panic!("'{}' was never defined", qualified_name));
+        self.nodes[node].definitions -= 1;
+    }
+
+    pub fn is_defined(&self, qualified_name: &str) -> bool {
+        self.find(TOP_LEVEL, qualified_name).is_some_and(|node| self.nodes[node].definitions > 0)
+    }
+
+    /// The qualified name `reference` refers to, looked up in each namespace it's nested in, most nested first.
+    pub fn resolve(&self, reference: &Constant) -> Option<Symbol> {
+        let name = reference.name.as_str();
+        if let Some(name) = name.strip_prefix("::") {
+            return self.resolve_in(TOP_LEVEL, name);
+        }
+
+        // namespaces nested deeper than the tree goes can't contain the constant, so the lookup starts at the deepest one that exists
+        let mut namespace = TOP_LEVEL;
+        for segment in reference.scope.iter().flat_map(|scope| scope.as_str().split("::")) {
+            match self.child(namespace, segment) {
+                Some(child) => namespace = child,
+                None => break,
+            }
+        }
+
+        loop {
+            if let Some(resolved) = self.resolve_in(namespace, name) {
+                return Some(resolved);
+            }
+            if namespace == TOP_LEVEL {
+                return None;
+            }
+            namespace = self.nodes[namespace].parent;
+        }
+    }
+
+    /// The constants and namespaces directly inside `namespace`, sorted, e.g. `Foo::Bar` and `Foo::Baz` for `Foo`.
+    /// An empty `namespace` is the top level.
+    pub fn children(&self, namespace: &str) -> Vec<Symbol> {
+        let node = if namespace.is_empty() { Some(TOP_LEVEL) } else { self.find(TOP_LEVEL, namespace) };
+
+        node.into_iter()
+            .flat_map(|node| self.nodes[node].children.values())
+            .map(|&child| self.nodes[child].name)
+            .sorted()
+            .collect()
+    }
+
+    fn resolve_in(&self, namespace: usize, name: &str) -> Option<Symbol> {
+        self.find(namespace, name).map(|node| &self.nodes[node]).filter(|node| node.definitions > 0).map(|node| node.name)
+    }
+
+    fn find(&self, namespace: usize, name: &str) -> Option<usize> {
+        name.split("::").try_fold(namespace, |node, segment| self.child(node, segment))
+    }
+
+    fn child(&self, node: usize, segment: &str) -> Option<usize> {
+        self.nodes[node].children.get(segment).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Namespace;
+    use crate::{
+        ast::{CaretPos, Constant, Loc},
+        intern::{PathSymbol, Symbol},
+    };
+
+    fn reference(scope: Option<&str>, name: &str) -> Constant {
+        let path = PathSymbol::intern(Path::new("/project/app/models/reference.rb"));
+        let caret = CaretPos { line: 1, column: 1 };
+
+        Constant {
+            scope: scope.map(Symbol::intern),
+            name: Symbol::intern(name),
+            loc: Loc {
+                path,
+                root_path: PathSymbol::intern(Path::new("/project")),
+                begin: caret,
+                end: caret,
+            },
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let namespace = Namespace::from_definitions(["Foo", "Foo::Bar", "Foo::Baz::Qux", "Bar", "Qux"]);
+        let resolve = |scope, name| namespace.resolve(&reference(scope, name)).map(Symbol::as_str);
+
+        assert_eq!(resolve(Some("Foo::Baz"), "Bar"), Some("Foo::Bar"));
+        assert_eq!(resolve(Some("Foo::Baz"), "Qux"), Some("Foo::Baz::Qux"));
+        assert_eq!(resolve(Some("Foo::Baz"), "::Qux"), Some("Qux"));
+        assert_eq!(resolve(Some("Foo::Missing::Deeper"), "Bar"), Some("Foo::Bar"));
+        assert_eq!(resolve(Some("Other"), "Bar"), Some("Bar"));
+        assert_eq!(resolve(None, "Foo::Baz::Qux"), Some("Foo::Baz::Qux"));
+        // a namespace that's never defined itself doesn't resolve
+        assert_eq!(resolve(Some("Foo"), "Baz"), None);
+        assert_eq!(resolve(None, "Missing"), None);
+    }
+
+    #[test]
+    fn test_define_and_undefine() {
+        let mut namespace = Namespace::from_definitions(["Foo", "Foo"]);
+
+        namespace.undefine("Foo");
+        assert!(namespace.is_defined("Foo"));
+        namespace.undefine("Foo");
+        assert!(!namespace.is_defined("Foo"));
+        assert_eq!(namespace.resolve(&reference(None, "Foo")), None);
+    }
+
+    #[test]
+    fn test_children() {
+        let namespace = Namespace::from_definitions(["Foo::Zed", "Foo::Bar::Baz", "Foo::Bar", "Other"]);
+
+        assert_eq!(namespace.children("Foo"), [Symbol::intern("Foo::Bar"), Symbol::intern("Foo::Zed")]);
+        assert_eq!(namespace.children(""), [Symbol::intern("Foo"), Symbol::intern("Other")]);
+        assert!(namespace.children("Missing").is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use itertools::Itertools;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::{
+    ast::{self, CaretPos, Loc},
+    cache,
+    intern::{PathSymbol, Symbol},
+    parser, resolver,
+    watch::{CheckedApplication, Session},
+    CliCommand,
+};
+
+/// Answers JSON-RPC 2.0 requests about the project on a Unix socket, one request per line. The project is kept up to
+/// date with the files on disk, and checked again before answering when files changed.
+pub fn serve(command: &CliCommand, parse_cache: Option<&cache::ParseCache>, socket_path: &Path) {
+    let listener = bind(socket_path);
+    let server = Server {
+        root_path: std::fs::canonicalize(&command.root_dir).unwrap(),
+        session: Mutex::new(Session::start(command, parse_cache)),
+    };
+    eprintln!("listening on {}", socket_path.display());
+
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = &server;
+                    scope.spawn(move || server.handle_connection(stream));
+                }
+                Err(error) => warn!("failed to accept a connection: {}", error),
+            }
+        }
+    });
+}
+
+/// Binds the socket, replacing a stale one left behind by a server that's no longer running.
+fn bind(socket_path: &Path) -> UnixListener {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            eprintln!("packlite is already serving on {}", socket_path.display());
+            std::process::exit(1);
+        }
+
+        std::fs::remove_file(socket_path).unwrap();
+    }
+
+    if let Some(parent) = socket_path.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+
+    UnixListener::bind(socket_path).unwrap_or_else(|error| panic!("failed to listen on {}: {}", socket_path.display(), error))
+}
+
+struct Server<'a> {
+    root_path: PathBuf,
+    session: Mutex<Session<'a>>,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    /// requests without an id are notifications, which get no response. A null id is still an id
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    fn invalid_params(message: impl ToString) -> Self {
+        Self {
+            code: -32602,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PathParams {
+    /// absolute, or relative to the root directory
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ConstantParams {
+    name: String,
+    /// the namespace the constant is referenced from, e.g. `Foo::Bar`
+    #[serde(default)]
+    scope: Option<String>,
+    /// the file the constant is referenced from, to pick the application it resolves in
+    #[serde(default)]
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct NamespaceParams {
+    /// e.g. `Foo::Bar`
+    #[serde(default)]
+    namespace: String,
+    /// a file of the application to look in
+    #[serde(default)]
+    path: Option<PathBuf>,
+}
+
+impl Server<'_> {
+    fn handle_connection(&self, stream: UnixStream) {
+        let mut writer = stream.try_clone().unwrap();
+
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => line,
+                Err(_) => return,
+            };
+
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    debug!("handling {}", request.method);
+                    let result = self.handle(&request.method, request.params);
+
+                    if request.id.is_none() {
+                        continue;
+                    }
+
+                    match result {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+                        Err(error) => json!({ "jsonrpc": "2.0", "id": request.id, "error": { "code": error.code, "message": error.message } }),
+                    }
+                }
+                Err(error) => json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32700, "message": error.to_string() } }),
+            };
+
+            if writeln!(writer, "{}", response).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn handle(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let mut session = self.session.lock().unwrap();
+        session.refresh();
+
+        match method {
+            // the violations of references in a file
+            "check_file" => {
+                let params: PathParams = parse_params(params)?;
+                let path = self.absolute_path(&params.path);
+                let application = application_of(&session, &path)?;
+
+                Ok(json!(application.violations.iter().filter(|violation| violation.reference.loc.path.as_path() == path).collect_vec()))
+            }
+            // the qualified name a constant resolves to and its definitions, or null
+            "resolve_constant" => {
+                let params: ConstantParams = parse_params(params)?;
+                let application = self.application_for(&session, params.path.as_deref())?;
+
+                Ok(resolve_constant(&application.project, session.namespace(application), params.name, params.scope))
+            }
+            // the constants and namespaces directly inside a namespace, the top level one when it's empty
+            "namespace_children" => {
+                let params: NamespaceParams = parse_params(params)?;
+                let application = self.application_for(&session, params.path.as_deref())?;
+                let namespace = session.namespace(application);
+
+                Ok(json!(namespace
+                    .children(&params.namespace)
+                    .into_iter()
+                    .map(|child| json!({ "name": child, "defined": namespace.is_defined(child.as_str()) }))
+                    .collect_vec()))
+            }
+            // the pack that owns a path
+            "owner" => {
+                let params: PathParams = parse_params(params)?;
+                let path = self.absolute_path(&params.path);
+                let application = application_of(&session, &path)?;
+                let package = parser::PackageOwnership::new(&application.project.packages).owner_of(&path, path.strip_prefix(&application.root_path).unwrap());
+
+                Ok(json!({ "pack": package.name, "root": package.root }))
+            }
+            // every violation, shaped like the output of `check`
+            "violations" => {
+                if session.has_applications {
+                    let violations_by_application: BTreeMap<&str, _> = session.applications.iter().map(|application| (application.name.as_str(), &application.violations)).collect();
+                    Ok(json!(violations_by_application))
+                } else {
+                    Ok(json!(session.applications[0].violations))
+                }
+            }
+            _ => Err(Error {
+                code: -32601,
+                message: format!("unknown method '{}'", method),
+            }),
+        }
+    }
+
+    /// The application of the file at `path`, which may only be left out when there's a single application.
+    fn application_for<'a>(&self, session: &'a Session, path: Option<&Path>) -> Result<&'a CheckedApplication, Error> {
+        match path {
+            Some(path) => application_of(session, &self.absolute_path(path)),
+            None if session.applications.len() == 1 => Ok(&session.applications[0]),
+            None => Err(Error::invalid_params("a path is needed to pick the application")),
+        }
+    }
+
+    fn absolute_path(&self, path: &Path) -> PathBuf {
+        let path = self.root_path.join(path);
+        std::fs::canonicalize(&path).unwrap_or(path)
+    }
+}
+
+/// Tells a null field apart from a missing one, which is left to `#[serde(default)]`.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, Error> {
+    serde_json::from_value(params).map_err(Error::invalid_params)
+}
+
+/// The application whose root is the closest ancestor of `path`.
+fn application_of<'a>(session: &'a Session, path: &Path) -> Result<&'a CheckedApplication, Error> {
+    session
+        .applications
+        .iter()
+        .filter(|application| path.starts_with(&application.root_path))
+        .max_by_key(|application| application.root_path.as_os_str().len())
+        .ok_or_else(|| Error::invalid_params(format!("{} isn't part of the project", path.display())))
+}
+
+fn resolve_constant(project: &parser::Project, namespace: &resolver::Namespace, name: String, scope: Option<String>) -> Value {
+    let reference = ast::Constant {
+        scope: scope.filter(|scope| !scope.is_empty()).map(|scope| Symbol::intern(&scope)),
+        name: Symbol::intern(&name),
+        loc: Loc {
+            path: PathSymbol::intern(Path::new("")),
+            root_path: PathSymbol::intern(Path::new("")),
+            begin: CaretPos { line: 0, column: 0 },
+            end: CaretPos { line: 0, column: 0 },
+        },
+    };
+
+    match resolver::resolve_reference(namespace, &reference) {
+        Some(resolved) => json!({ "name": resolved.name, "definitions": project.definitions.iter().filter(|definition| definition.name == resolved.name).collect_vec() }),
+        None => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+        sync::Mutex,
+        time::Duration,
+    };
+
+    use clap::Parser;
+    use serde_json::{json, Value};
+
+    use super::Server;
+    use crate::{watch::Session, CliCommand};
+
+    #[test]
+    fn test_requests() {
+        let root_path = std::env::temp_dir().join(format!("packlite-serve-test-{}", std::process::id()));
+        for (path, contents) in [
+            ("package.yml", "enforce_dependencies: false\nenforce_privacy: false\n"),
+            ("packs/a/package.yml", "enforce_dependencies: false\nenforce_privacy: true\n"),
+            ("packs/a/app/models/a/secret.rb", "module A\n  class Secret; end\nend\n"),
+            ("packs/b/package.yml", "enforce_dependencies: false\nenforce_privacy: false\n"),
+            ("packs/b/app/models/b.rb", "class B\n  A::Secret\nend\n"),
+        ] {
+            std::fs::create_dir_all(root_path.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root_path.join(path), contents).unwrap();
+        }
+        let root_path = std::fs::canonicalize(root_path).unwrap();
+
+        let command = CliCommand::parse_from(["packlite", "--root-dir", root_path.to_str().unwrap(), "--no-cache", "serve"]);
+        let server = Server {
+            root_path: root_path.clone(),
+            session: Mutex::new(Session::start(&command, None)),
+        };
+        let responses = std::thread::scope(|scope| {
+            // the client is dropped even when a response is missing, which ends the connection so the test can fail
+            let (mut client, stream) = UnixStream::pair().unwrap();
+            client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            let mut lines = BufReader::new(client.try_clone().unwrap()).lines();
+            let server = &server;
+            scope.spawn(move || server.handle_connection(stream));
+
+            [
+                json!({ "jsonrpc": "2.0", "id": 1, "method": "check_file", "params": { "path": "packs/b/app/models/b.rb" } }),
+                json!({ "jsonrpc": "2.0", "id": 2, "method": "resolve_constant", "params": { "name": "Secret", "scope": "A" } }),
+                json!({ "jsonrpc": "2.0", "id": 3, "method": "namespace_children", "params": { "namespace": "A" } }),
+                json!({ "jsonrpc": "2.0", "id": 4, "method": "owner", "params": { "path": "packs/a/app/models/a/secret.rb" } }),
+                // a notification gets no response, so the next line answers the request after it
+                json!({ "jsonrpc": "2.0", "method": "violations" }),
+                json!({ "jsonrpc": "2.0", "id": null, "method": "violations" }),
+                json!({ "jsonrpc": "2.0", "id": "unknown", "method": "rename_constant" }),
+                json!({ "jsonrpc": "2.0", "id": 5, "method": "owner", "params": {} }),
+            ]
+            .map(|request| {
+                writeln!(client, "{}", request).unwrap();
+                if request.get("id").is_none() {
+                    return Value::Null;
+                }
+                serde_json::from_str::<Value>(&lines.next().unwrap().unwrap()).unwrap()
+            })
+        });
+        std::fs::remove_dir_all(&root_path).unwrap();
+
+        let [check_file, resolve_constant, namespace_children, owner, notification, null_id, unknown_method, invalid_params] = responses;
+        assert_eq!(check_file["id"], 1);
+        assert_eq!(check_file["result"].as_array().unwrap().len(), 1);
+        assert_eq!(check_file["result"][0]["violation_type"], "privacy");
+        assert_eq!(resolve_constant["result"]["name"], "A::Secret");
+        assert_eq!(resolve_constant["result"]["definitions"][0]["package"], "packs/a");
+        assert_eq!(namespace_children["result"], json!([{ "name": "A::Secret", "defined": true }]));
+        assert_eq!(owner["result"], json!({ "pack": "packs/a", "root": root_path.join("packs/a") }));
+        assert_eq!(notification, Value::Null);
+        assert_eq!(null_id["id"], Value::Null);
+        assert_eq!(null_id["result"], check_file["result"]);
+        assert_eq!(
+            unknown_method,
+            json!({ "jsonrpc": "2.0", "id": "unknown", "error": { "code": -32601, "message": "unknown method 'rename_constant'" } })
+        );
+        assert_eq!(invalid_params["error"]["code"], -32602);
+    }
+}
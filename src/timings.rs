@@ -0,0 +1,334 @@
+use std::{
+    cell::Cell,
+    fmt,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use serde::Serialize;
+use tracing::{
+    field::{Field, Visit},
+    span, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// The spans whose wall time counts towards each phase, by target and name, so spans of dependencies that happen to
+/// share a name don't count. Phases overlap: the walk parses files as it finds them, so its time can't be told apart
+/// from parsing and it's reported along with it.
+const PHASES: [(&str, &[(&str, &str)]); 6] = [
+    ("walk+parse", &[("packlite::files", "walkdir")]),
+    (
+        "parsing",
+        &[
+            ("packlite::parser", "parse_ruby_files"),
+            ("packlite::low_memory", "parse_ruby_files"),
+            ("packlite::incremental", "changes"),
+            PARSE_FILE_SPAN,
+        ],
+    ),
+    ("packages", &[("packlite::files", "all_packages")]),
+    (
+        "resolution",
+        &[
+            ("packlite::resolver", "resolve_references"),
+            ("packlite::low_memory", "index_definitions"),
+            ("packlite::low_memory", "resolve_references"),
+            ("packlite::incremental", "index"),
+            ("packlite::incremental", "resolve"),
+        ],
+    ),
+    ("metadata", &[("packlite::parser", "apply_package_metadata"), ("packlite::incremental", "project")]),
+    ("validation", &[("packlite::validator", "validate"), ("packlite::incremental", "validate")]),
+];
+
+/// The span around parsing a single file, which records the path of the file.
+const PARSE_FILE_SPAN: (&str, &str) = ("packlite::ast::parser", "parse_text");
+
+/// Records every span of the run, to report how long each phase took or to write them as a Chrome trace.
+pub struct Profiler {
+    start: Instant,
+    spans: Arc<Mutex<Vec<ClosedSpan>>>,
+}
+
+struct ClosedSpan {
+    name: &'static str,
+    target: &'static str,
+    thread: u64,
+    /// since the profiler started
+    start: Duration,
+    duration: Duration,
+    path: Option<String>,
+}
+
+/// Kept in the extensions of a span while it's open.
+struct OpenSpan {
+    start: Instant,
+    thread: u64,
+    path: Option<String>,
+}
+
+pub struct ProfilerLayer {
+    start: Instant,
+    spans: Arc<Mutex<Vec<ClosedSpan>>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            spans: Arc::default(),
+        }
+    }
+
+    pub fn layer(&self) -> ProfilerLayer {
+        ProfilerLayer {
+            start: self.start,
+            spans: Arc::clone(&self.spans),
+        }
+    }
+
+    /// Wall time of each phase, in the order they run.
+    pub fn phases(&self) -> Vec<(&'static str, Duration)> {
+        let spans = self.spans.lock().unwrap();
+
+        PHASES
+            .iter()
+            .map(|(phase, phase_spans)| {
+                let intervals = spans
+                    .iter()
+                    .filter(|span| phase_spans.contains(&(span.target, span.name)))
+                    .map(|span| (span.start, span.start + span.duration));
+                (*phase, wall_time(intervals))
+            })
+            .collect()
+    }
+
+    /// The `count` files that took the longest to parse, slowest first.
+    pub fn slowest_files(&self, count: usize) -> Vec<(String, Duration)> {
+        let spans = self.spans.lock().unwrap();
+
+        spans
+            .iter()
+            .filter(|span| (span.target, span.name) == PARSE_FILE_SPAN)
+            .sorted_by(|a, b| b.duration.cmp(&a.duration))
+            .take(count)
+            .map(|span| (span.path.clone().unwrap_or_default(), span.duration))
+            .collect()
+    }
+
+    /// Prints the wall time of each phase, and the `slowest_files` files that took the longest to parse, to stderr.
+    pub fn print_timings(&self, slowest_files: usize) {
+        eprintln!("timings (phases overlap, files are parsed while the walk goes on):");
+        for (phase, duration) in self.phases() {
+            eprintln!("  {:<12}{:>9.3}s", phase, duration.as_secs_f64());
+        }
+        eprintln!("  {:<12}{:>9.3}s", "total", self.start.elapsed().as_secs_f64());
+
+        let slowest_files = self.slowest_files(slowest_files);
+        if !slowest_files.is_empty() {
+            eprintln!("slowest files to parse:");
+            for (path, duration) in slowest_files {
+                eprintln!("  {:>9.3}s  {}", duration.as_secs_f64(), path);
+            }
+        }
+    }
+
+    /// Writes every span as a complete event of the Chrome trace event format, which chrome://tracing and Perfetto open.
+    pub fn write_trace(&self, path: &Path) {
+        let spans = self.spans.lock().unwrap();
+        let events = spans
+            .iter()
+            .map(|span| TraceEvent {
+                name: span.name,
+                cat: span.target,
+                ph: "X",
+                ts: span.start.as_secs_f64() * 1e6,
+                dur: span.duration.as_secs_f64() * 1e6,
+                pid: std::process::id(),
+                tid: span.thread,
+                args: TraceArgs { path: span.path.as_deref() },
+            })
+            .collect_vec();
+
+        let file = File::create(path).unwrap_or_else(|error| panic!("failed to create {}: {}", path.display(), error));
+        serde_json::to_writer(BufWriter::new(file), &Trace { trace_events: events }).unwrap_or_else(|error| panic!("failed to write {}: {}", path.display(), error));
+    }
+}
+
+/// What to report about the run once it's done.
+pub struct Report {
+    pub profiler: Profiler,
+    pub timings: bool,
+    pub slowest_files: usize,
+    pub trace_file: Option<PathBuf>,
+}
+
+impl Report {
+    pub fn finish(self) {
+        if self.timings {
+            self.profiler.print_timings(self.slowest_files);
+        }
+        if let Some(trace_file) = &self.trace_file {
+            self.profiler.write_trace(trace_file);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Trace<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: Vec<TraceEvent<'a>>,
+}
+
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    cat: &'a str,
+    ph: &'a str,
+    /// microseconds
+    ts: f64,
+    dur: f64,
+    pid: u32,
+    tid: u64,
+    args: TraceArgs<'a>,
+}
+
+#[derive(Serialize)]
+struct TraceArgs<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+}
+
+/// How long at least one of the intervals was running.
+fn wall_time(intervals: impl Iterator<Item = (Duration, Duration)>) -> Duration {
+    let mut total = Duration::ZERO;
+    let mut current: Option<(Duration, Duration)> = None;
+
+    for (start, end) in intervals.sorted() {
+        current = match current {
+            Some((current_start, current_end)) if start <= current_end => Some((current_start, current_end.max(end))),
+            _ => {
+                total += current.map_or(Duration::ZERO, |(start, end)| end - start);
+                Some((start, end))
+            }
+        };
+    }
+
+    total + current.map_or(Duration::ZERO, |(start, end)| end - start)
+}
+
+static THREADS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static THREAD: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// A small number for the current thread, as trace viewers show thread ids.
+fn thread_id() -> u64 {
+    THREAD.with(|thread| match thread.get() {
+        Some(id) => id,
+        None => {
+            let id = THREADS.fetch_add(1, Ordering::Relaxed);
+            thread.set(Some(id));
+            id
+        }
+    })
+}
+
+struct PathVisitor<'a>(&'a mut Option<String>);
+
+impl Visit for PathVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "path" {
+            *self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ProfilerLayer {
+    fn on_new_span(&self, attributes: &span::Attributes<'_>, id: &span::Id, context: Context<'_, S>) {
+        let mut path = None;
+        attributes.record(&mut PathVisitor(&mut path));
+
+        let span = context.span(id).expect("a new span is in the registry");
+        span.extensions_mut().insert(OpenSpan {
+            start: Instant::now(),
+            thread: thread_id(),
+            path,
+        });
+    }
+
+    fn on_close(&self, id: span::Id, context: Context<'_, S>) {
+        let span = context.span(&id).expect("a closing span is in the registry");
+        let Some(open_span) = span.extensions_mut().remove::<OpenSpan>() else {
+            return;
+        };
+
+        self.spans.lock().unwrap().push(ClosedSpan {
+            name: span.name(),
+            target: span.metadata().target(),
+            thread: open_span.thread,
+            start: open_span.start - self.start,
+            duration: open_span.start.elapsed(),
+            path: open_span.path,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{wall_time, Profiler};
+    use crate::{
+        ast,
+        files::{FileSource, InMemory},
+        resolver,
+    };
+
+    #[test]
+    fn test_wall_time() {
+        let seconds = Duration::from_secs;
+        let intervals = [(seconds(5), seconds(7)), (seconds(0), seconds(2)), (seconds(1), seconds(3)), (seconds(6), seconds(6))];
+
+        assert_eq!(wall_time(intervals.into_iter()), seconds(5));
+        assert_eq!(wall_time(std::iter::empty()), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_records_phases_and_files() {
+        let profiler = Profiler::new();
+        let source = InMemory::new(Path::new("/project"), [("app/models/foo.rb", "class Foo; end"), ("app/models/bar.rb", "class Bar; Foo; end")]);
+
+        // the subscriber is only the default on this thread, so nothing here runs on rayon's threads
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(profiler.layer()), || {
+            let parsed_files = source.files().ruby_files.iter().map(|path| ast::parse_ast(&source, path)).collect();
+            resolver::resolve_references(parsed_files, &[], &resolver::Scope::all());
+            // a span of another crate that has the name of one of ours
+            tracing::info_span!(target: "other_crate", "validate").in_scope(|| std::thread::sleep(Duration::from_millis(1)));
+        });
+
+        let phases = profiler.phases();
+        assert_eq!(
+            phases.iter().map(|(phase, _)| *phase).collect::<Vec<_>>(),
+            ["walk+parse", "parsing", "packages", "resolution", "metadata", "validation"]
+        );
+        assert!(phases[1].1 > Duration::ZERO);
+        assert!(phases[3].1 > Duration::ZERO);
+        assert_eq!(phases[5].1, Duration::ZERO);
+
+        let mut slowest_files = profiler.slowest_files(10).into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+        slowest_files.sort();
+        assert_eq!(slowest_files, ["app/models/bar.rb", "app/models/foo.rb"]);
+        assert_eq!(profiler.slowest_files(1).len(), 1);
+    }
+}
@@ -1,17 +1,20 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     path::PathBuf,
 };
 
+use globset::GlobSet;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
 
 use crate::{
-    files::Package,
+    files::{self, Metadata, Package},
+    intern::Symbol,
     parser::{self, Definition, Reference},
 };
 
-#[derive(Deserialize, Serialize, PartialOrd, Ord, PartialEq, Eq, Clone, Hash)]
+#[derive(Deserialize, Serialize, PartialOrd, Ord, PartialEq, Eq, Clone, Hash, Debug)]
 pub enum ViolationType {
     #[serde(rename = "dependency")]
     Dependency,
@@ -19,21 +22,32 @@ pub enum ViolationType {
     Privacy,
 }
 
-#[derive(Serialize)]
+impl std::fmt::Display for ViolationType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ViolationType::Dependency => write!(f, "dependency"),
+            ViolationType::Privacy => write!(f, "privacy"),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
 pub struct Violation {
-    violation_type: ViolationType,
-    violated_pack: String,
-    violating_pack: String,
-    definition: Definition,
-    reference: Reference,
+    pub violation_type: ViolationType,
+    pub violated_pack: Symbol,
+    pub violated_pack_metadata: Metadata,
+    pub violating_pack: Symbol,
+    pub violating_pack_metadata: Metadata,
+    pub definition: Definition,
+    pub reference: Reference,
+    /// the violated pack enforces this check strictly, so the violation can't be recorded as deprecated
+    pub strict: bool,
 }
 
 struct ValidationContext<'a> {
-    definition_by_name: HashMap<&'a String, Vec<&'a Definition>>,
-    definition_by_package: HashMap<&'a String, Vec<&'a Definition>>,
-    reference_by_name: HashMap<&'a String, Vec<&'a Reference>>,
-    reference_by_package: HashMap<&'a String, Vec<&'a Reference>>,
-    package_by_name: HashMap<&'a String, &'a Package>,
+    definition_by_name: HashMap<Symbol, Vec<&'a Definition>>,
+    package_by_name: HashMap<Symbol, &'a Package>,
+    dependencies_by_package: HashMap<Symbol, GlobSet>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -50,63 +64,156 @@ pub struct DeprecatedReferences {
 impl<'a> ValidationContext<'a> {
     fn from_project(project: &'a parser::Project) -> Self {
         Self {
-            definition_by_name: project.definitions.iter().into_grouping_map_by(|definition| &definition.name).collect(),
-            definition_by_package: project.definitions.iter().into_grouping_map_by(|definition| &definition.package).collect(),
-            reference_by_name: project.references.iter().into_grouping_map_by(|reference| &reference.name).collect(),
-            reference_by_package: project.references.iter().into_grouping_map_by(|reference| &reference.package).collect(),
-            package_by_name: project.packages.iter().map(|package| (&package.name, package)).collect(),
+            definition_by_name: project.definitions.iter().into_grouping_map_by(|definition| definition.name).collect(),
+            package_by_name: project.packages.iter().map(|package| (package.name, package)).collect(),
+            dependencies_by_package: project
+                .packages
+                .iter()
+                .filter_map(|package| package.dependencies.as_ref().map(|dependencies| (package.name, files::package_glob_set(dependencies))))
+                .collect(),
         }
     }
 
-    fn package(&self, package: &String) -> &Package {
-        self.package_by_name.get(package).unwrap()
+    fn package(&self, package: Symbol) -> &Package {
+        self.package_by_name.get(&package).unwrap()
     }
 
-    fn all_references_in_package(&self, package: &String) -> Option<Vec<&Reference>> {
-        self.reference_by_package.get(package).map(Vec::to_owned)
+    fn dependencies(&self, package: Symbol) -> Option<&GlobSet> {
+        self.dependencies_by_package.get(&package)
     }
 
-    fn all_definitions_in_package(&self, package: &String) -> Option<Vec<&Definition>> {
-        self.definition_by_package.get(package).map(Vec::to_owned)
+    fn all_definitions_for(&self, name: Symbol) -> Option<Vec<&Definition>> {
+        self.definition_by_name.get(&name).map(Vec::to_owned)
     }
+}
+
+/// References into the root package are only checked with `enforce_root`, then the root package.yml's own settings apply.
+/// `every_reference` is whether the project holds the references of every file, see `warn_about_configuration`.
+#[instrument(skip_all)]
+pub fn validate(project: &parser::Project, enforce_root: bool, every_reference: bool) -> Vec<Violation> {
+    let (violations, ignored_violations) = validate_packages(project, &project.packages.iter().collect_vec(), enforce_root);
+    warn_about_configuration(project, &ignored_violations, every_reference);
 
-    fn all_definitions_for(&self, name: &String) -> Option<Vec<&Definition>> {
-        self.definition_by_name.get(name).map(Vec::to_owned)
+    violations
+}
+
+/// The violations of references made from `packages`, and separately the ones their ignore lists suppress.
+pub fn validate_packages(project: &parser::Project, packages: &[&Package], enforce_root: bool) -> (Vec<Violation>, Vec<Violation>) {
+    let pack_validator = PackValidator::new(project, enforce_root);
+    let reference_by_package = project.references.iter().into_group_map_by(|reference| reference.package);
+
+    let mut violations = Vec::new();
+    let mut ignored_violations = Vec::new();
+    for package in packages {
+        let references = reference_by_package.get(&package.name).map(Vec::as_slice).unwrap_or_default();
+        let (mut package_violations, mut package_ignored_violations) = pack_validator.validate(package, references);
+
+        violations.append(&mut package_violations);
+        ignored_violations.append(&mut package_ignored_violations);
     }
 
-    fn packages_with_definition(&self, definition: &String) -> Vec<&String> {
-        self.definition_by_name.get(definition).unwrap().iter().map(|definition| &definition.package).unique().collect()
+    (violations, ignored_violations)
+}
+
+/// Validates the references of one pack at a time against the definitions of the project, so the references of
+/// every pack don't have to be in memory at once.
+pub struct PackValidator<'a> {
+    validation_context: ValidationContext<'a>,
+    enforce_root: bool,
+}
+
+impl<'a> PackValidator<'a> {
+    /// Only the packages and definitions of `project` are used.
+    pub fn new(project: &'a parser::Project, enforce_root: bool) -> Self {
+        Self {
+            validation_context: ValidationContext::from_project(project),
+            enforce_root,
+        }
     }
 
-    fn packages_referencing(&self, definition: &String) -> Vec<&String> {
-        self.reference_by_name.get(definition).unwrap().iter().map(|reference| &reference.package).unique().collect()
+    /// The violations of `references`, all made from `package`, and separately the ones its ignore lists suppress.
+    #[instrument(skip_all)]
+    pub fn validate(&self, package: &Package, references: &[&Reference]) -> (Vec<Violation>, Vec<Violation>) {
+        let mut violations = privacy_violation(package, references, &self.validation_context);
+        violations.append(&mut dependency_violation(package, references, &self.validation_context));
+
+        violations
+            .into_iter()
+            .filter(|violation| self.enforce_root || violation.violated_pack != *files::ROOT_PACKAGE_NAME)
+            .partition(|violation| !is_ignored(violation, &self.validation_context))
     }
 }
 
-pub fn validate(project: &parser::Project) -> Vec<Violation> {
-    let validation_context = ValidationContext::from_project(project);
-    project
-        .packages
-        .iter()
-        .flat_map(|package| {
-            let mut violations = vec![];
+/// Warns about package.yml entries that don't do anything, given every ignored violation in the project.
+/// Ignores are only reported as unnecessary when `ignored_violations` come from `every_reference` of the project, as
+/// ignores that the unchecked files need would look unnecessary otherwise.
+pub fn warn_about_configuration(project: &parser::Project, ignored_violations: &[Violation], every_reference: bool) {
+    warn_about_unmatched_dependencies(project);
+    if every_reference {
+        warn_about_unnecessary_ignores(project, ignored_violations);
+    }
+}
 
-            violations.append(&mut privacy_violation(package, &validation_context));
-            violations.append(&mut dependency_violation(package, &validation_context));
+fn is_ignored(violation: &Violation, validation_context: &ValidationContext) -> bool {
+    match violation.violation_type {
+        ViolationType::Privacy => validation_context
+            .package(violation.violated_pack)
+            .ignored_private_constants
+            .iter()
+            .any(|constant| violation.definition.name == *constant),
+        ViolationType::Dependency => validation_context
+            .package(violation.violating_pack)
+            .ignored_dependencies
+            .iter()
+            .any(|constant| violation.definition.name == *constant),
+    }
+}
 
-            violations
+fn warn_about_unnecessary_ignores(project: &parser::Project, ignored_violations: &[Violation]) {
+    // the pack whose ignore list suppressed each violation, along with the constant
+    let used: HashSet<(ViolationType, Symbol, Symbol)> = ignored_violations
+        .iter()
+        .map(|violation| match violation.violation_type {
+            ViolationType::Privacy => (ViolationType::Privacy, violation.violated_pack, violation.definition.name),
+            ViolationType::Dependency => (ViolationType::Dependency, violation.violating_pack, violation.definition.name),
         })
-        .filter(|violation| violation.violated_pack != "root")
-        .collect()
+        .collect();
+
+    for package in &project.packages {
+        for constant in &package.ignored_private_constants {
+            if !used.contains(&(ViolationType::Privacy, package.name, Symbol::intern(constant))) {
+                warn!("ignored_private_constants entry '::{}' in '{}' is no longer needed", constant, package.name);
+            }
+        }
+
+        for constant in &package.ignored_dependencies {
+            if !used.contains(&(ViolationType::Dependency, package.name, Symbol::intern(constant))) {
+                warn!("ignored_dependencies entry '::{}' in '{}' is no longer needed", constant, package.name);
+            }
+        }
+    }
 }
 
-pub fn deprecated_references(violations: &[Violation]) -> Vec<DeprecatedReferences> {
+fn warn_about_unmatched_dependencies(project: &parser::Project) {
+    for package in &project.packages {
+        for dependency in package.dependencies.iter().flatten() {
+            let glob = files::package_glob_set(std::slice::from_ref(dependency));
+
+            if !project.packages.iter().any(|other| glob.is_match(other.name.as_str())) {
+                warn!("dependency '{}' of '{}' does not match any pack", dependency, package.name);
+            }
+        }
+    }
+}
+
+pub fn deprecated_references(violations: &[&Violation]) -> Vec<DeprecatedReferences> {
     violations
         .iter()
-        .into_group_map_by(|violation| &violation.violating_pack)
+        .copied()
+        .into_group_map_by(|violation| violation.violating_pack)
         .into_iter()
         .map(|(violating_pack, violations)| DeprecatedReferences {
-            violating_pack: violating_pack.to_owned(),
+            violating_pack: violating_pack.as_str().to_owned(),
             deprecated_references: deprecated_references_for_pack(&violations),
         })
         .collect()
@@ -115,9 +222,9 @@ pub fn deprecated_references(violations: &[Violation]) -> Vec<DeprecatedReferenc
 fn deprecated_references_for_pack(violations: &[&Violation]) -> BTreeMap<String, BTreeMap<String, DeprecatedReference>> {
     violations
         .iter()
-        .into_group_map_by(|violation| &violation.violated_pack)
+        .into_group_map_by(|violation| violation.violated_pack)
         .into_iter()
-        .sorted_by_key(|(violated_pack, _)| violated_pack.clone())
+        .sorted_by_key(|(violated_pack, _)| *violated_pack)
         .map(|(violated_pack, violations)| {
             let pack_violations = violations
                 .into_iter()
@@ -134,16 +241,16 @@ fn deprecated_references_for_pack(violations: &[&Violation]) -> BTreeMap<String,
                 })
                 .collect();
 
-            (violated_pack.to_owned(), pack_violations)
+            (violated_pack.as_str().to_owned(), pack_violations)
         })
         .collect()
 }
 
-fn privacy_violation(package: &Package, validation_context: &ValidationContext) -> Vec<Violation> {
+fn privacy_violation(package: &Package, references: &[&Reference], validation_context: &ValidationContext) -> Vec<Violation> {
     let mut violations = Vec::new();
 
-    for reference in validation_context.all_references_in_package(&package.name).unwrap_or_default() {
-        let definitions = validation_context.all_definitions_for(&reference.name).unwrap_or_default();
+    for &reference in references {
+        let definitions = validation_context.all_definitions_for(reference.name).unwrap_or_default();
 
         // a namespace constant tends to be re-defined many times, sometimes as a public constant and sometimes as private
         // to avoid false positives, if it has a single definition that's public, treat all have them as allowed
@@ -152,7 +259,7 @@ fn privacy_violation(package: &Package, validation_context: &ValidationContext)
         }
 
         let private_definitions = definitions.iter().filter(|definition| {
-            if !validation_context.package(&definition.package).enforce_privacy {
+            if !validation_context.package(definition.package).enforce_privacy.is_enabled() {
                 return false;
             }
 
@@ -162,10 +269,13 @@ fn privacy_violation(package: &Package, validation_context: &ValidationContext)
         for definition in private_definitions {
             violations.push(Violation {
                 violation_type: ViolationType::Privacy,
-                violated_pack: definition.package.clone(),
-                violating_pack: package.name.clone(),
+                violated_pack: definition.package,
+                violated_pack_metadata: validation_context.package(definition.package).metadata.clone(),
+                violating_pack: package.name,
+                violating_pack_metadata: package.metadata.clone(),
                 definition: (*definition).to_owned(),
                 reference: reference.to_owned(),
+                strict: validation_context.package(definition.package).enforce_privacy.is_strict(),
             })
         }
     }
@@ -173,13 +283,13 @@ fn privacy_violation(package: &Package, validation_context: &ValidationContext)
     violations
 }
 
-fn dependency_violation(package: &Package, validation_context: &ValidationContext) -> Vec<Violation> {
+fn dependency_violation(package: &Package, references: &[&Reference], validation_context: &ValidationContext) -> Vec<Violation> {
     let mut violations = Vec::new();
 
-    for reference in validation_context.all_references_in_package(&package.name).unwrap_or_default() {
-        let definitions = validation_context.all_definitions_for(&reference.name).unwrap_or_default();
+    for &reference in references {
+        let definitions = validation_context.all_definitions_for(reference.name).unwrap_or_default();
         let dependency_violations = definitions.iter().filter(|definition| {
-            if !validation_context.package(&definition.package).enforce_dependencies {
+            if !validation_context.package(definition.package).enforce_dependencies.is_enabled() {
                 return false;
             }
 
@@ -187,8 +297,8 @@ fn dependency_violation(package: &Package, validation_context: &ValidationContex
                 return false;
             }
 
-            if let Some(dependencies) = &package.dependencies {
-                !dependencies.contains(&definition.package)
+            if let Some(dependencies) = validation_context.dependencies(package.name) {
+                !dependencies.is_match(definition.package.as_str())
             } else {
                 true
             }
@@ -197,13 +307,127 @@ fn dependency_violation(package: &Package, validation_context: &ValidationContex
         for definition in dependency_violations {
             violations.push(Violation {
                 violation_type: ViolationType::Dependency,
-                violated_pack: definition.package.clone(),
-                violating_pack: package.name.clone(),
+                violated_pack: definition.package,
+                violated_pack_metadata: validation_context.package(definition.package).metadata.clone(),
+                violating_pack: package.name,
+                violating_pack_metadata: package.metadata.clone(),
                 definition: (*definition).to_owned(),
                 reference: reference.to_owned(),
+                strict: validation_context.package(definition.package).enforce_dependencies.is_strict(),
             })
         }
     }
 
     violations
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use itertools::Itertools;
+
+    use crate::{
+        files::{self, InMemory},
+        parser, resolver,
+    };
+
+    fn violations(project_files: &[(&str, &str)]) -> Vec<String> {
+        violations_with_root(project_files, false)
+    }
+
+    fn project(project_files: &[(&str, &str)]) -> parser::Project {
+        let source = InMemory::new(Path::new("/project"), project_files.iter().copied());
+        let packages = files::all_packages(&source, &[]).unwrap();
+        let (definitions, references) = resolver::resolve_references(parser::parse_ruby_files(&source, None), &[], &resolver::Scope::all());
+        parser::apply_package_metadata(definitions, references, packages, "app/public", &[])
+    }
+
+    fn violations_with_root(project_files: &[(&str, &str)], enforce_root: bool) -> Vec<String> {
+        super::validate(&project(project_files), enforce_root, true)
+            .into_iter()
+            .map(|violation| {
+                format!(
+                    "{} {} -> {} ::{}",
+                    violation.violation_type, violation.violating_pack, violation.violated_pack, violation.definition.name
+                )
+            })
+            .sorted()
+            .collect()
+    }
+
+    const ROOT_PACKAGE: (&str, &str) = ("package.yml", "enforce_dependencies: false\nenforce_privacy: false\n");
+
+    #[test]
+    fn test_privacy_and_dependency_violations() {
+        let violations = violations(&[
+            ROOT_PACKAGE,
+            ("packs/a/package.yml", "enforce_dependencies: true\nenforce_privacy: true\ndependencies: []\n"),
+            ("packs/a/app/models/a.rb", "class A\n  def run\n    B\n    BApi\n  end\nend\n"),
+            ("packs/b/package.yml", "enforce_dependencies: true\nenforce_privacy: true\n"),
+            ("packs/b/app/models/b.rb", "class B\nend\n"),
+            ("packs/b/app/public/b_api.rb", "class BApi\nend\n"),
+        ]);
+
+        assert_eq!(
+            violations,
+            vec!["dependency packs/a -> packs/b ::B", "dependency packs/a -> packs/b ::BApi", "privacy packs/a -> packs/b ::B"]
+        );
+    }
+
+    #[test]
+    fn test_dependency_globs_and_ignores() {
+        let violations = violations(&[
+            ROOT_PACKAGE,
+            (
+                "packs/a/package.yml",
+                "enforce_dependencies: true\nenforce_privacy: true\ndependencies:\n  - packs/platform/*\nignored_dependencies:\n  - \"::C\"\n",
+            ),
+            ("packs/a/app/models/a.rb", "class A\n  def run\n    B\n    C\n  end\nend\n"),
+            (
+                "packs/platform/b/package.yml",
+                "enforce_dependencies: true\nenforce_privacy: true\nignored_private_constants:\n  - \"::B\"\n",
+            ),
+            ("packs/platform/b/app/models/b.rb", "class B\nend\n"),
+            ("packs/c/package.yml", "enforce_dependencies: true\nenforce_privacy: false\n"),
+            ("packs/c/app/models/c.rb", "class C\nend\n"),
+        ]);
+
+        assert!(violations.is_empty(), "{:?}", violations);
+    }
+
+    #[test]
+    fn test_enforce_root() {
+        let project_files = [
+            ("package.yml", "enforce_dependencies: false\nenforce_privacy: true\n"),
+            ("app/models/root_model.rb", "class RootModel\nend\n"),
+            ("packs/a/package.yml", "enforce_dependencies: true\nenforce_privacy: true\n"),
+            ("packs/a/app/models/a.rb", "class A\n  def run\n    RootModel\n  end\nend\n"),
+        ];
+
+        assert!(violations_with_root(&project_files, false).is_empty());
+        assert_eq!(violations_with_root(&project_files, true), vec!["privacy packs/a -> root ::RootModel"]);
+    }
+
+    #[test]
+    fn test_violations_carry_pack_metadata() {
+        let project = project(&[
+            ROOT_PACKAGE,
+            ("packs/a/package.yml", "enforce_dependencies: true\nenforce_privacy: false\nmetadata:\n  owner: team-a\n  tier: 1\n"),
+            ("packs/a/app/models/a.rb", "class A\n  def run\n    B\n  end\nend\n"),
+            ("packs/b/package.yml", "enforce_dependencies: true\nenforce_privacy: false\nmetadata:\n  owner: team-b\n"),
+            ("packs/b/app/models/b.rb", "class B\nend\n"),
+        ]);
+        let violations = super::validate(&project, false, true);
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].violating_pack_metadata["owner"], "team-a");
+        assert_eq!(violations[0].violated_pack_metadata["owner"], "team-b");
+
+        let filters = |filters: &[&str]| filters.iter().map(|filter| filter.parse().unwrap()).collect::<Vec<files::MetadataFilter>>();
+        let package_a = project.package("packs/a").unwrap();
+        assert!(package_a.matches_metadata(&filters(&["owner=team-a", "tier=1"])));
+        assert!(!package_a.matches_metadata(&filters(&["owner=team-b"])));
+        assert!(!project.package("packs/b").unwrap().matches_metadata(&filters(&["tier=1"])));
+    }
+}
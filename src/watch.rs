@@ -0,0 +1,244 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use itertools::Itertools;
+use notify::{RecursiveMode, Watcher};
+use tracing::{debug, warn};
+
+use crate::{
+    application_index, applications, cache, config, exit_on_error, file_source, files, incremental, load_packages, matching_metadata, parser, resolver, validator, walk_options, Application,
+    CliCommand,
+};
+
+/// How long to wait for more changes after the first one, so saving many files at once leads to a single pass.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Checks the project, then keeps checking it whenever files change, only printing the violations that appeared or
+/// cleared since the previous pass.
+pub fn watch(command: &CliCommand, parse_cache: Option<&cache::ParseCache>) {
+    let mut session = Session::start(command, parse_cache);
+    let mut reported = BTreeSet::new();
+
+    loop {
+        let violations: BTreeSet<String> = session
+            .applications
+            .iter()
+            .flat_map(|application| application.violations.iter().map(|violation| describe(violation, session.application_path(application))))
+            .collect();
+
+        for violation in reported.difference(&violations) {
+            println!("- {}", violation);
+        }
+        for violation in violations.difference(&reported) {
+            println!("+ {}", violation);
+        }
+        std::io::stdout().flush().unwrap();
+        reported = violations;
+
+        session.wait_for_changes();
+    }
+}
+
+/// The checked project, kept up to date with the files on disk. The resolved project of every application stays
+/// in memory, so a change only parses, resolves and validates again what it affects.
+pub struct Session<'a> {
+    command: &'a CliCommand,
+    parse_cache: Option<&'a cache::ParseCache>,
+    // dropping the watcher stops the notifications
+    _watcher: notify::RecommendedWatcher,
+    receiver: mpsc::Receiver<notify::Result<notify::Event>>,
+    /// changes under these paths, e.g. to the cache, don't affect the project
+    ignored_paths: Vec<PathBuf>,
+    /// the applications of the configuration last loaded, along with their files
+    configured_applications: Vec<Application>,
+    sources: Vec<Box<dyn files::FileSource>>,
+    /// what changed since the last successful check, everything when `None`
+    pending_paths: Option<HashSet<PathBuf>>,
+    engines: HashMap<String, incremental::Engine>,
+    pub has_applications: bool,
+    pub applications: Vec<CheckedApplication>,
+}
+
+pub struct CheckedApplication {
+    pub name: String,
+    /// canonical root directory of the application
+    pub root_path: PathBuf,
+    pub project: parser::Project,
+    /// the violations made by packs that match `--metadata`
+    pub violations: Vec<validator::Violation>,
+}
+
+impl<'a> Session<'a> {
+    /// Starts watching the root directory and checks the project.
+    pub fn start(command: &'a CliCommand, parse_cache: Option<&'a cache::ParseCache>) -> Self {
+        let root_path = std::fs::canonicalize(&command.root_dir).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).unwrap_or_else(|error| panic!("failed to watch {}: {}", root_path.display(), error));
+        watcher
+            .watch(&root_path, RecursiveMode::Recursive)
+            .unwrap_or_else(|error| panic!("failed to watch {}: {}", root_path.display(), error));
+
+        let mut session = Self {
+            command,
+            parse_cache,
+            _watcher: watcher,
+            receiver,
+            ignored_paths: vec![
+                root_path.join(".git"),
+                root_path.join(config::load(&root_path, command.rev.as_deref()).unwrap_or_default().cache_directory),
+            ],
+            configured_applications: Vec::new(),
+            sources: Vec::new(),
+            pending_paths: None,
+            engines: HashMap::new(),
+            has_applications: false,
+            applications: Vec::new(),
+        };
+        exit_on_error(session.check(None));
+        session
+    }
+
+    /// Blocks until files change, then checks the project again.
+    pub fn wait_for_changes(&mut self) {
+        loop {
+            let event = self.receiver.recv().expect("the file watcher stopped");
+
+            if let Some(changed_paths) = self.changed_paths(event) {
+                return self.check_changes(changed_paths);
+            }
+        }
+    }
+
+    /// Checks the project again if files changed since the last check, without waiting for changes.
+    pub fn refresh(&mut self) {
+        while let Ok(event) = self.receiver.try_recv() {
+            if let Some(changed_paths) = self.changed_paths(event) {
+                return self.check_changes(changed_paths);
+            }
+        }
+    }
+
+    /// The path to show paths relative to `application` from the root directory.
+    pub fn application_path<'b>(&self, application: &'b CheckedApplication) -> &'b Path {
+        if self.has_applications {
+            Path::new(&application.name)
+        } else {
+            Path::new("")
+        }
+    }
+
+    /// What references in `application` are resolved against, kept up to date between checks.
+    pub fn namespace(&self, application: &CheckedApplication) -> &resolver::Namespace {
+        self.engines[&application.name].namespace()
+    }
+
+    /// The paths changed by `first_event` and the events that follow it, if any matter to the project.
+    fn changed_paths(&self, first_event: notify::Result<notify::Event>) -> Option<HashSet<PathBuf>> {
+        let mut events = vec![first_event];
+        while let Ok(event) = self.receiver.recv_timeout(DEBOUNCE) {
+            events.push(event);
+        }
+
+        let mut changed_paths = HashSet::new();
+        for event in events {
+            match event {
+                Ok(event) => changed_paths.extend(event.paths.into_iter().filter(|path| !self.ignored_paths.iter().any(|ignored| path.starts_with(ignored)))),
+                Err(error) => warn!("file watcher error: {}", error),
+            }
+        }
+
+        debug!("{} paths changed", changed_paths.len());
+        (!changed_paths.is_empty()).then_some(changed_paths)
+    }
+
+    /// Errors, e.g. in a package.yml being edited, leave the results of the last check in place until they're fixed.
+    fn check_changes(&mut self, changed_paths: HashSet<PathBuf>) {
+        if let Err(error) = self.check(Some(changed_paths)) {
+            warn!("{}, keeping the results of the last check", error);
+        }
+    }
+
+    /// Package.yml files are read again every time, packlite.yml files and ruby files only if they're in `changed_paths`,
+    /// or new. Changes are kept until a check succeeds, so one that fails doesn't lose them.
+    fn check(&mut self, changed_paths: Option<HashSet<PathBuf>>) -> Result<(), String> {
+        let command = self.command;
+
+        if let Some(changed_paths) = &changed_paths {
+            for source in &mut self.sources {
+                source.update(changed_paths);
+            }
+        }
+        self.pending_paths = match (self.pending_paths.take(), changed_paths) {
+            (Some(mut pending_paths), Some(changed_paths)) => {
+                pending_paths.extend(changed_paths);
+                Some(pending_paths)
+            }
+            _ => None,
+        };
+
+        let is_config_file = |path: &PathBuf| path.file_name().is_some_and(|file_name| file_name == config::CONFIG_FILE_NAME);
+        if self.pending_paths.as_ref().is_none_or(|pending_paths| pending_paths.iter().any(is_config_file)) {
+            let config = config::load(&command.root_dir, command.rev.as_deref())?;
+            let has_applications = !config.applications.is_empty();
+            let applications = applications(command, config)?;
+
+            self.sources = applications
+                .iter()
+                .map(|application| file_source(command, &application.root_path, &walk_options(command, application), &None))
+                .collect::<Result<_, _>>()?;
+            self.configured_applications = applications;
+            self.has_applications = has_applications;
+        }
+
+        let packages = self.sources.iter().map(|source| load_packages(command, source.as_ref())).collect::<Result<Vec<_>, _>>()?;
+        let changed_paths = self.pending_paths.replace(HashSet::new());
+        let (applications, sources) = (&self.configured_applications, &self.sources);
+        let ignored_constants = command.ignored_constants();
+
+        self.engines.retain(|name, _| applications.iter().any(|application| &application.name == name));
+        self.applications.clear();
+
+        for ((application, source), packages) in applications.iter().zip(sources).zip(packages) {
+            let engine = self.engines.entry(application.name.clone()).or_insert_with(|| match self.parse_cache {
+                Some(parse_cache) => incremental::Engine::load(&parse_cache.state_path(source.root(), command.rev.as_deref())),
+                None => incremental::Engine::default(),
+            });
+
+            let external_sources = application.uses.iter().map(|used| sources[application_index(applications, used)].as_ref()).collect_vec();
+            let settings = incremental::Settings {
+                packages: &packages,
+                public_path: &command.public_path,
+                ignore_constants: &ignored_constants,
+                enforce_root: application.config.enforce_root,
+            };
+            let (project, violations) = engine.refresh(source.as_ref(), &external_sources, changed_paths.as_ref(), self.parse_cache, &settings);
+
+            self.applications.push(CheckedApplication {
+                name: application.name.clone(),
+                root_path: source.root().to_owned(),
+                violations: matching_metadata(command, &project, violations),
+                project,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn describe(violation: &validator::Violation, application_path: &Path) -> String {
+    format!(
+        "{} violation: '{}' references '::{}' from '{}' in {}:{}",
+        violation.violation_type,
+        violation.violating_pack,
+        violation.definition.name,
+        violation.violated_pack,
+        application_path.join(violation.reference.loc.relative_path()).display(),
+        violation.reference.loc.begin.line
+    )
+}